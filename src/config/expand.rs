@@ -0,0 +1,115 @@
+// `~` and environment variable expansion for path settings, so one config
+// works across machines and user names
+
+/// Expand a leading `~` and every `$VAR` or `${VAR}` in `path`.
+///
+/// `$HOME` and the XDG base directories fall back to their defaults when
+/// unset or empty, as the base directory spec says; any other unset
+/// variable is an error. A `$` not followed by a name, or followed by a
+/// digit, is kept as is.
+pub fn expand(path: &str) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = path;
+    if let Some(after) = path.strip_prefix('~') {
+        // `~user` isn't supported and is left alone
+        if after.is_empty() || after.starts_with('/') {
+            expanded.push_str(&variable("HOME")?);
+            rest = after;
+        }
+    }
+
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let (name, remainder) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => return Err(format!("unclosed '${{' in '{}'", path)),
+            },
+            // Names can't start with a digit, so `$1abc` is literal
+            None if after.starts_with(|c: char| c.is_ascii_digit()) => ("", after),
+            None => {
+                let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if name.is_empty() {
+            expanded.push('$');
+            rest = after;
+        } else {
+            expanded.push_str(&variable(name)?);
+            rest = remainder;
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn variable(name: &str) -> Result<String, String> {
+    let value = std::env::var(name).ok();
+    let home = dirs::home_dir();
+    let fallback = match name {
+        "HOME" => home,
+        "XDG_CONFIG_HOME" => home.map(|home| home.join(".config")),
+        "XDG_CACHE_HOME" => home.map(|home| home.join(".cache")),
+        "XDG_DATA_HOME" => home.map(|home| home.join(".local/share")),
+        "XDG_STATE_HOME" => home.map(|home| home.join(".local/state")),
+        _ => return value.ok_or_else(|| format!("environment variable {} is not set", name)),
+    };
+    value.filter(|value| !value.is_empty())
+        .or_else(|| fallback.map(|path| path.to_string_lossy().into_owned()))
+        .ok_or_else(|| format!("environment variable {} is not set", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn home() -> String {
+        dirs::home_dir().unwrap().to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn tilde_is_home_but_not_another_user() {
+        assert_eq!(expand("~").unwrap(), home());
+        assert_eq!(expand("~/wallpapers").unwrap(), format!("{}/wallpapers", home()));
+        assert_eq!(expand("~alice/wallpapers").unwrap(), "~alice/wallpapers");
+        assert_eq!(expand("/srv/~/x").unwrap(), "/srv/~/x");
+    }
+
+    #[test]
+    fn braced_and_bare_variables() {
+        // Names used only by this test, since the environment is shared
+        std::env::set_var("DEPARTURE_EXPAND_DIR", "/srv/themes");
+        assert_eq!(expand("$DEPARTURE_EXPAND_DIR/dark.css").unwrap(), "/srv/themes/dark.css");
+        assert_eq!(expand("${DEPARTURE_EXPAND_DIR}_old").unwrap(), "/srv/themes_old");
+        // Without braces the name runs on to the underscore
+        assert!(expand("$DEPARTURE_EXPAND_DIR_old").unwrap_err().contains("DEPARTURE_EXPAND_DIR_old"));
+        assert!(expand("${DEPARTURE_EXPAND_UNSET}").unwrap_err().contains("DEPARTURE_EXPAND_UNSET is not set"));
+    }
+
+    #[test]
+    fn dollars_without_a_name_are_literal() {
+        assert_eq!(expand("/tmp/$").unwrap(), "/tmp/$");
+        assert_eq!(expand("/tmp/$/x").unwrap(), "/tmp/$/x");
+        assert_eq!(expand("/tmp/$$").unwrap(), "/tmp/$$");
+        assert_eq!(expand("/tmp/$1abc").unwrap(), "/tmp/$1abc");
+    }
+
+    #[test]
+    fn unclosed_braces_are_an_error() {
+        assert_eq!(expand("/tmp/${HOME").unwrap_err(), "unclosed '${' in '/tmp/${HOME'");
+    }
+
+    #[test]
+    fn xdg_directories_fall_back_when_unset_or_empty() {
+        // Nothing else reads XDG_STATE_HOME
+        std::env::remove_var("XDG_STATE_HOME");
+        assert_eq!(expand("$XDG_STATE_HOME/departure").unwrap(), format!("{}/.local/state/departure", home()));
+        std::env::set_var("XDG_STATE_HOME", "");
+        assert_eq!(expand("${XDG_STATE_HOME}/departure").unwrap(), format!("{}/.local/state/departure", home()));
+        std::env::set_var("XDG_STATE_HOME", "/var/state");
+        assert_eq!(expand("$XDG_STATE_HOME/departure").unwrap(), "/var/state/departure");
+        std::env::remove_var("XDG_STATE_HOME");
+    }
+}
@@ -0,0 +1,292 @@
+// Layered configuration: system, user, drop-in and per-host files
+//
+// Files are merged in order, each one over the ones before it:
+//
+//   1. `departure/config.*` in each of $XDG_CONFIG_DIRS (default /etc/xdg)
+//   2. the user's config, e.g. ~/.config/departure/config.toml
+//   3. `config.d/*` next to the user's config, by file name
+//   4. `config.<hostname>.*` next to the user's config
+//
+// Tables merge key by key and actions merge by name; any other value,
+// including lists such as `theme.source`, replaces the earlier one.
+
+use super::CONFIG_FILES;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Where a value in the merged config was set
+#[derive(Debug, Clone)]
+pub struct Origin {
+    pub file: PathBuf,
+    /// Line and column, for JSON and TOML files
+    pub location: Option<(usize, usize)>,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some((line, _)) = self.location {
+            write!(f, ":{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// The config files that exist for `user_config`, lowest precedence first
+pub fn files(user_config: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = system_dirs().iter().rev()
+        .filter_map(|dir| first_existing(&dir.join("departure"), CONFIG_FILES))
+        .collect();
+
+    if user_config.exists() {
+        files.push(user_config.to_path_buf());
+    }
+
+    let dir = user_config.parent().unwrap_or(Path::new("."));
+    files.extend(drop_ins(&dir.join("config.d")));
+    if let Some(host) = hostname() {
+        let names: Vec<String> = ["toml", "yaml", "json"].iter()
+            .map(|extension| format!("config.{}.{}", host, extension))
+            .collect();
+        files.extend(first_existing(dir, &names));
+    }
+
+    // Running with --config pointing at a system file shouldn't apply it twice
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.canonicalize().unwrap_or_else(|_| file.clone())));
+    files
+}
+
+/// $XDG_CONFIG_DIRS, most important first
+fn system_dirs() -> Vec<PathBuf> {
+    let dirs = std::env::var("XDG_CONFIG_DIRS").unwrap_or_default();
+    let dirs: Vec<PathBuf> = dirs.split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from).collect();
+    if dirs.is_empty() { vec![PathBuf::from("/etc/xdg")] } else { dirs }
+}
+
+fn first_existing(dir: &Path, names: &[impl AsRef<Path>]) -> Option<PathBuf> {
+    names.iter().map(|name| dir.join(name)).find(|path| path.is_file())
+}
+
+/// Config files in a drop-in directory, sorted by name
+fn drop_ins(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| matches!(path.extension().and_then(|extension| extension.to_str()), Some("json" | "toml" | "yaml" | "yml")))
+        .collect();
+    files.sort();
+    files
+}
+
+/// The short host name, e.g. "laptop" for laptop.example.com
+fn hostname() -> Option<String> {
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())?;
+    let name = name.trim().split('.').next().unwrap_or_default().to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+/// One parsed config file
+pub struct Layer {
+    pub file: PathBuf,
+    pub value: Value,
+    /// Positions of its keys, by path within the file
+    pub locations: HashMap<String, (usize, usize)>,
+}
+
+/// The merged config and where each of its values came from
+pub struct Merged {
+    pub value: Value,
+    /// By path in the merged config, e.g. `actions[2].command`. Values
+    /// no file sets are missing, and take their default.
+    pub origins: HashMap<String, Origin>,
+}
+
+pub fn merge(layers: Vec<Layer>) -> Merged {
+    let mut merged = Map::new();
+    let mut origins = HashMap::new();
+    for layer in layers {
+        let Value::Object(object) = layer.value else {
+            continue;
+        };
+        let mut merger = Merger { file: &layer.file, locations: &layer.locations, origins: &mut origins };
+        merger.object(&mut merged, object, "", "");
+    }
+    Merged { value: Value::Object(merged), origins }
+}
+
+struct Merger<'a> {
+    file: &'a Path,
+    locations: &'a HashMap<String, (usize, usize)>,
+    origins: &'a mut HashMap<String, Origin>,
+}
+
+impl Merger<'_> {
+    /// Merge `layer` into `base`. `path` is where they are in the merged
+    /// config and `layer_path` where the layer's part is in its own file;
+    /// they only differ for actions merged by name.
+    fn object(&mut self, base: &mut Map<String, Value>, layer: Map<String, Value>, path: &str, layer_path: &str) {
+        for (key, value) in layer {
+            let child = join(path, &key);
+            let layer_child = join(layer_path, &key);
+            match (base.get_mut(&key), value) {
+                (Some(Value::Object(base)), Value::Object(layer)) => self.object(base, layer, &child, &layer_child),
+                (Some(Value::Array(base)), Value::Array(layer)) if child == "actions" => self.actions(base, layer),
+                (_, value) => {
+                    self.replace(&child, &layer_child, &value);
+                    base.insert(key, value);
+                }
+            }
+        }
+    }
+
+    /// Actions with a name already in `base` are merged into it, the rest are added after it
+    fn actions(&mut self, base: &mut Vec<Value>, layer: Vec<Value>) {
+        for (index, action) in layer.into_iter().enumerate() {
+            let layer_path = format!("actions[{}]", index);
+            let name = action.get("name").and_then(Value::as_str);
+            let existing = base.iter().position(|other| name.is_some() && other.get("name").and_then(Value::as_str) == name);
+            match (existing.map(|position| (position, &mut base[position])), action) {
+                (Some((position, Value::Object(base))), Value::Object(mut action)) => {
+                    // The name stays where the action was first defined
+                    action.remove("name");
+                    self.object(base, action, &format!("actions[{}]", position), &layer_path);
+                }
+                (_, action) => {
+                    self.replace(&format!("actions[{}]", base.len()), &layer_path, &action);
+                    base.push(action);
+                }
+            }
+        }
+    }
+
+    /// Forget where the old value at `path` came from and record the new one
+    fn replace(&mut self, path: &str, layer_path: &str, value: &Value) {
+        self.origins.retain(|key, _| !within(key, path));
+        self.record(path, layer_path, value);
+    }
+
+    fn record(&mut self, path: &str, layer_path: &str, value: &Value) {
+        let origin = Origin { file: self.file.to_path_buf(), location: self.locations.get(layer_path).copied() };
+        self.origins.insert(path.to_string(), origin);
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object {
+                    self.record(&join(path, key), &join(layer_path, key), value);
+                }
+            }
+            Value::Array(array) => {
+                for (index, value) in array.iter().enumerate() {
+                    self.record(&format!("{}[{}]", path, index), &format!("{}[{}]", layer_path, index), value);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+pub(super) fn join(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+/// Whether `key` is `path` or something inside it
+fn within(key: &str, path: &str) -> bool {
+    key.strip_prefix(path).is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn layer(file: &str, value: Value, locations: &[(&str, (usize, usize))]) -> Layer {
+        Layer {
+            file: PathBuf::from(file),
+            value,
+            locations: locations.iter().map(|(path, location)| (path.to_string(), *location)).collect(),
+        }
+    }
+
+    fn origin(merged: &Merged, path: &str) -> Option<(String, Option<(usize, usize)>)> {
+        merged.origins.get(path).map(|origin| (origin.file.display().to_string(), origin.location))
+    }
+
+    #[test]
+    fn later_layers_take_over_origins() {
+        let merged = merge(vec![
+            layer("system.toml", json!({ "theme": { "scheme": "dark", "source": ["file", "system", "manual"] } }),
+                &[("theme.scheme", (2, 1)), ("theme.source", (3, 1))]),
+            layer("user.toml", json!({ "theme": { "scheme": "light", "source": ["wallpaper"] } }),
+                &[("theme.scheme", (5, 3)), ("theme.source", (6, 3))]),
+        ]);
+        assert_eq!(merged.value["theme"]["scheme"], "light");
+        assert_eq!(origin(&merged, "theme.scheme"), Some(("user.toml".to_string(), Some((5, 3)))));
+        assert_eq!(origin(&merged, "theme.source"), Some(("user.toml".to_string(), Some((6, 3)))));
+        assert_eq!(origin(&merged, "theme.source[0]").map(|(file, _)| file), Some("user.toml".to_string()));
+        // The longer list it replaced leaves nothing behind
+        assert_eq!(origin(&merged, "theme.source[1]"), None);
+        assert_eq!(origin(&merged, "theme.source[2]"), None);
+        // The table itself was first set by the system file
+        assert_eq!(origin(&merged, "theme").map(|(file, _)| file), Some("system.toml".to_string()));
+    }
+
+    #[test]
+    fn actions_merge_by_name_in_their_first_position() {
+        let merged = merge(vec![
+            layer("system.toml", json!({ "actions": [
+                { "name": "Lock", "command": "swaylock" },
+                { "name": "Logout", "command": "loginctl terminate-user $USER" },
+            ] }), &[("actions[1].name", (6, 1)), ("actions[1].command", (7, 1))]),
+            layer("user.toml", json!({ "actions": [
+                { "name": "Logout", "command": "hyprctl dispatch exit" },
+            ] }), &[("actions[0].name", (2, 1)), ("actions[0].command", (3, 1))]),
+        ]);
+        let actions = merged.value["actions"].as_array().unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[1]["name"], "Logout");
+        assert_eq!(actions[1]["command"], "hyprctl dispatch exit");
+        assert_eq!(origin(&merged, "actions[1].command"), Some(("user.toml".to_string(), Some((3, 1)))));
+        assert_eq!(origin(&merged, "actions[1].name"), Some(("system.toml".to_string(), Some((6, 1)))));
+        assert_eq!(origin(&merged, "actions[0].command").map(|(file, _)| file), Some("system.toml".to_string()));
+    }
+
+    #[test]
+    fn unmatched_actions_are_appended() {
+        let merged = merge(vec![
+            layer("system.toml", json!({ "actions": [{ "name": "Lock", "command": "swaylock" }] }), &[]),
+            layer("user.toml", json!({ "actions": [
+                { "command": "systemctl suspend" },
+                { "name": "Reboot", "command": "systemctl reboot" },
+            ] }), &[("actions[0]", (2, 1)), ("actions[1].command", (6, 1))]),
+        ]);
+        let actions = merged.value["actions"].as_array().unwrap();
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[1]["command"], "systemctl suspend");
+        assert_eq!(actions[2]["name"], "Reboot");
+        assert_eq!(origin(&merged, "actions[1]"), Some(("user.toml".to_string(), Some((2, 1)))));
+        assert_eq!(origin(&merged, "actions[2].command"), Some(("user.toml".to_string(), Some((6, 1)))));
+    }
+
+    #[test]
+    fn a_system_file_passed_as_the_user_config_is_read_once() {
+        let dir = std::env::temp_dir().join(format!("departure-layers-{}", std::process::id()));
+        let system = dir.join("departure");
+        std::fs::create_dir_all(&system).unwrap();
+        std::fs::write(system.join("config.toml"), "").unwrap();
+        // Nothing else reads XDG_CONFIG_DIRS
+        std::env::set_var("XDG_CONFIG_DIRS", &dir);
+
+        let files = files(&system.join("..").join("departure").join("config.toml"));
+        std::env::remove_var("XDG_CONFIG_DIRS");
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, vec![system.join("config.toml")]);
+    }
+}
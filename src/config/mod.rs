@@ -1,8 +1,19 @@
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use anyhow::Result;
 
+pub mod expand;
+pub mod layers;
+pub mod validate;
+
+use layers::Origin;
+
+// Every section and field has a default, so a config file only needs to
+// contain what it changes.
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub theme: ThemeConfig,
     pub layout: LayoutConfig,
@@ -11,26 +22,242 @@ pub struct Config {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ThemeConfig {
-    pub source: String, // "manual", "system", "file", "command"
+    pub source: ThemeSource, // one source or a list tried in order
     pub manual_colors: Option<ManualColors>,
     pub file_path: Option<PathBuf>,
     pub command: Option<String>,
+    pub command_timeout: u64, // milliseconds before the theme or wallpaper command is killed
+    pub seed: Option<String>, // seed color for the "seed" source, e.g. "#6750a4"
+    pub preset: Option<String>, // built-in palette for the "preset" source, e.g. "nord"
+    pub wallpaper: Option<PathBuf>,
+    pub wallpaper_command: Option<String>, // prints the current wallpaper, e.g. "swww query"
     pub watch_file: bool,
+    pub scheme: Option<Scheme>, // default: auto, follows the desktop
+    pub pywal_mapping: Option<BTreeMap<String, String>>, // role -> pywal slot, e.g. "primary": "color4"
+    pub base16_mapping: Option<BTreeMap<String, String>>, // role -> base16 slot, e.g. "primary": "base0D"
+    pub terminal_mapping: Option<BTreeMap<String, String>>, // role -> terminal slot for pywal/xresources/kitty/foot/alacritty
+    pub min_contrast: Option<f64>, // WCAG ratio for text, default 4.5 (AA)
+    pub auto_contrast: bool, // adjust text and on_* colors that miss min_contrast
+    pub high_contrast: bool, // AAA text, AA borders and an opaque background
+    pub css_template: Option<PathBuf>, // replaces the built-in stylesheet
+    pub user_stylesheet: Option<PathBuf>, // loaded on top of the generated stylesheet
+}
+
+/// Where theme colors come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    Manual,
+    System,
+    File,
+    Command,
+    Seed,
+    Wallpaper,
+    Preset,
+    Base16,
+    Xresources,
+    Kitty,
+    Foot,
+    Alacritty,
+}
+
+impl SourceKind {
+    pub const ALL: &'static [SourceKind] = &[
+        SourceKind::Manual,
+        SourceKind::System,
+        SourceKind::File,
+        SourceKind::Command,
+        SourceKind::Seed,
+        SourceKind::Wallpaper,
+        SourceKind::Preset,
+        SourceKind::Base16,
+        SourceKind::Xresources,
+        SourceKind::Kitty,
+        SourceKind::Foot,
+        SourceKind::Alacritty,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SourceKind::Manual => "manual",
+            SourceKind::System => "system",
+            SourceKind::File => "file",
+            SourceKind::Command => "command",
+            SourceKind::Seed => "seed",
+            SourceKind::Wallpaper => "wallpaper",
+            SourceKind::Preset => "preset",
+            SourceKind::Base16 => "base16",
+            SourceKind::Xresources => "xresources",
+            SourceKind::Kitty => "kitty",
+            SourceKind::Foot => "foot",
+            SourceKind::Alacritty => "alacritty",
+        }
+    }
 }
 
+impl std::fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for SourceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SourceKind::ALL.iter()
+            .copied()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = SourceKind::ALL.iter().map(|kind| kind.as_str()).collect();
+                format!("unknown theme source '{}' (expected one of: {})", s, names.join(", "))
+            })
+    }
+}
+
+/// A single theme source or an ordered fallback chain, e.g. `["file", "manual"]`
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ThemeSource {
+    Single(SourceKind),
+    Chain(Vec<SourceKind>),
+}
+
+impl ThemeSource {
+    pub fn sources(&self) -> &[SourceKind] {
+        match self {
+            ThemeSource::Single(source) => std::slice::from_ref(source),
+            ThemeSource::Chain(sources) => sources,
+        }
+    }
+}
+
+impl std::str::FromStr for ThemeSource {
+    type Err = String;
+
+    /// Parse a source name or a comma-separated chain
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let sources = s.split(',')
+            .map(|source| source.trim().parse())
+            .collect::<Result<Vec<SourceKind>, _>>()?;
+        Ok(match <[SourceKind; 1]>::try_from(sources) {
+            Ok([source]) => ThemeSource::Single(source),
+            Err(sources) => ThemeSource::Chain(sources),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ManualColors {
     pub background: String,
     pub primary: String,
     pub secondary: String,
     pub text: String,
     pub danger: String,
+    // Optional semantic roles, derived from the five above when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surface: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surface_variant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outline: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_primary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_danger: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dialog_background: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<String>,
+}
+
+impl ManualColors {
+    /// The configured colors by role name, skipping unset optional roles
+    pub fn roles(&self) -> Vec<(&'static str, &str)> {
+        let mut roles = vec![
+            ("background", self.background.as_str()),
+            ("primary", self.primary.as_str()),
+            ("secondary", self.secondary.as_str()),
+            ("text", self.text.as_str()),
+            ("danger", self.danger.as_str()),
+        ];
+        let optional = [
+            ("surface", &self.surface),
+            ("surface_variant", &self.surface_variant),
+            ("outline", &self.outline),
+            ("on_primary", &self.on_primary),
+            ("on_danger", &self.on_danger),
+            ("focus", &self.focus),
+            ("dialog_background", &self.dialog_background),
+            ("shadow", &self.shadow),
+        ];
+        roles.extend(optional.into_iter().filter_map(|(role, value)| value.as_deref().map(|value| (role, value))));
+        roles
+    }
+}
+
+/// Which variant of a theme to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    Dark,
+    Light,
+    Amoled,
+    /// Follow the desktop's dark/light preference
+    Auto,
+}
+
+impl Scheme {
+    pub const ALL: &'static [Scheme] = &[
+        Scheme::Dark,
+        Scheme::Light,
+        Scheme::Amoled,
+        Scheme::Auto,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scheme::Dark => "dark",
+            Scheme::Light => "light",
+            Scheme::Amoled => "amoled",
+            Scheme::Auto => "auto",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutType {
+    Horizontal,
+    Vertical,
+    Grid,
+}
+
+impl LayoutType {
+    pub const ALL: &'static [LayoutType] = &[
+        LayoutType::Horizontal,
+        LayoutType::Vertical,
+        LayoutType::Grid,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LayoutType::Horizontal => "horizontal",
+            LayoutType::Vertical => "vertical",
+            LayoutType::Grid => "grid",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct LayoutConfig {
-    pub layout_type: String, // "horizontal", "vertical", "grid"
+    pub layout_type: LayoutType,
     pub button_size: u32,
     pub button_spacing: u32,
     pub margin: u32,
@@ -38,6 +265,7 @@ pub struct LayoutConfig {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct EffectsConfig {
     pub blur: bool,
     pub animations: bool,
@@ -51,7 +279,9 @@ pub struct ActionConfig {
     pub command: String,
     pub icon: String,
     pub keybind: Option<String>,
+    #[serde(default)]
     pub confirm: bool,
+    #[serde(default)]
     pub danger: bool,
 }
 
@@ -118,11 +348,25 @@ impl Default for Config {
 impl Default for ThemeConfig {
     fn default() -> Self {
         Self {
-            source: "manual".to_string(),
+            source: ThemeSource::Single(SourceKind::Manual),
             manual_colors: Some(ManualColors::default()),
             file_path: None,
             command: None,
+            command_timeout: 5000,
+            seed: None,
+            preset: None,
+            wallpaper: None,
+            wallpaper_command: None,
             watch_file: false,
+            scheme: None,
+            pywal_mapping: None,
+            base16_mapping: None,
+            terminal_mapping: None,
+            min_contrast: None,
+            auto_contrast: false,
+            high_contrast: false,
+            css_template: None,
+            user_stylesheet: None,
         }
     }
 }
@@ -135,6 +379,14 @@ impl Default for ManualColors {
             secondary: "#74c7ec".to_string(),
             text: "#cdd6f4".to_string(),
             danger: "#f38ba8".to_string(),
+            surface: None,
+            surface_variant: None,
+            outline: None,
+            on_primary: None,
+            on_danger: None,
+            focus: None,
+            dialog_background: None,
+            shadow: None,
         }
     }
 }
@@ -142,7 +394,7 @@ impl Default for ManualColors {
 impl Default for LayoutConfig {
     fn default() -> Self {
         Self {
-            layout_type: "horizontal".to_string(),
+            layout_type: LayoutType::Horizontal,
             button_size: 80,
             button_spacing: 20,
             margin: 50,
@@ -162,24 +414,179 @@ impl Default for EffectsConfig {
     }
 }
 
+/// The commented default config written by `--generate-config --format toml`
+pub const DEFAULT_TOML: &str = include_str!("default.toml");
+
+/// Config file names looked for in the config directory, in order
+const CONFIG_FILES: &[&str] = &["config.toml", "config.yaml", "config.json"];
+
+/// Config file syntax, chosen by file extension or with --format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// `.toml`, `.yaml` or `.yml`; anything else is read as JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "json",
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Yaml => "yaml",
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned>(self, content: &str) -> Result<T> {
+        Ok(match self {
+            ConfigFormat::Json => serde_json::from_str(content)?,
+            ConfigFormat::Toml => toml::from_str(content)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(content)?,
+        })
+    }
+
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<String> {
+        Ok(match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(value)?,
+            ConfigFormat::Toml => toml::to_string_pretty(value)?,
+            ConfigFormat::Yaml => serde_yaml::to_string(value)?,
+        })
+    }
+}
+
+/// `~/.config/departure`
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir().unwrap_or_else(|| PathBuf::from(".")).join("departure")
+}
+
+/// The first of config.toml, config.yaml and config.json that exists in the
+/// config directory, or config.json if there is none
+pub fn find_config() -> PathBuf {
+    let dir = config_dir();
+    CONFIG_FILES.iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| dir.join("config.json"))
+}
+
+/// The effective config and the files it was merged from
+pub struct Layered {
+    pub config: Config,
+    /// Lowest precedence first
+    pub files: Vec<PathBuf>,
+    /// Where each value was set, by path; see [`layers::Merged`]
+    pub origins: HashMap<String, Origin>,
+}
+
 impl Config {
-    pub fn load(path: &PathBuf) -> Result<Self> {
-        if path.exists() {
-            let content = std::fs::read_to_string(path)?;
-            let config: Config = serde_json::from_str(&content)?;
-            Ok(config)
-        } else {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::load_layered(path)?.config)
+    }
+
+    /// Load `path` merged over the system config, with the drop-in and
+    /// per-host files next to it merged on top
+    pub fn load_layered(path: &Path) -> Result<Layered> {
+        let files = layers::files(path);
+        if files.is_empty() {
             log::info!("Config file not found at {}, using defaults", path.display());
-            Ok(Config::default())
+            return Ok(Layered { config: Config::default(), files, origins: HashMap::new() });
+        }
+        for file in &files {
+            log::debug!("Loading config from {}", file.display());
+        }
+
+        let (merged, diagnostics) = validate::validate(&files);
+        for diagnostic in &diagnostics {
+            log::warn!("{}", diagnostic);
         }
+        // The validator's diagnostics say more than serde's first error
+        let invalid = |e: anyhow::Error| {
+            match diagnostics.iter().find(|diagnostic| diagnostic.severity == validate::Severity::Error) {
+                Some(diagnostic) => anyhow::anyhow!("Invalid config {}", diagnostic),
+                None => e,
+            }
+        };
+        let merged = merged.ok_or_else(|| invalid(anyhow::anyhow!("Invalid config")))?;
+        let mut config: Config = serde_json::from_value(merged.value).map_err(|e| invalid(e.into()))?;
+        config.expand_paths();
+        Ok(Layered { config, files, origins: merged.origins })
     }
 
+    /// Expand `~` and environment variables in every path setting. A path
+    /// that can't be expanded is left as written; the validator has already
+    /// warned about it.
+    fn expand_paths(&mut self) {
+        let theme = &mut self.theme;
+        let paths = [&mut theme.file_path, &mut theme.wallpaper, &mut theme.css_template, &mut theme.user_stylesheet];
+        for path in paths.into_iter().flatten() {
+            if let Some(expanded) = path.to_str().and_then(|path| expand::expand(path).ok()) {
+                *path = PathBuf::from(expanded);
+            }
+        }
+        // Icon names have neither `~` nor `$`, so only icon files change
+        for action in &mut self.actions {
+            if let Ok(expanded) = expand::expand(&action.icon) {
+                action.icon = expanded;
+            }
+        }
+    }
+
+    /// Write the config in the format its extension asks for
     pub fn save(&self, path: &PathBuf) -> Result<()> {
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
         }
-        let content = serde_json::to_string_pretty(self)?;
+        let content = ConfigFormat::from_path(path).serialize(self)?;
         std::fs::write(path, content)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Config has no PartialEq; its serialized form is what has to match
+    fn value(config: &Config) -> serde_json::Value {
+        serde_json::to_value(config).unwrap()
+    }
+
+    #[test]
+    fn names_match_serde() {
+        // The validator and --help list these names; serde is what reads them
+        let serialized = |value: serde_json::Value| value.as_str().unwrap().to_string();
+        for kind in SourceKind::ALL {
+            assert_eq!(serialized(serde_json::to_value(kind).unwrap()), kind.as_str());
+        }
+        for scheme in Scheme::ALL {
+            assert_eq!(serialized(serde_json::to_value(scheme).unwrap()), scheme.as_str());
+        }
+        for layout in LayoutType::ALL {
+            assert_eq!(serialized(serde_json::to_value(layout).unwrap()), layout.as_str());
+        }
+    }
+
+    #[test]
+    fn default_toml_matches_the_defaults() {
+        let config: Config = toml::from_str(DEFAULT_TOML).unwrap();
+        assert_eq!(value(&config), value(&Config::default()));
+    }
+
+    #[test]
+    fn defaults_round_trip_in_every_format() {
+        for format in [ConfigFormat::Json, ConfigFormat::Toml, ConfigFormat::Yaml] {
+            let content = format.serialize(&Config::default()).unwrap();
+            let config: Config = format.parse(&content).unwrap();
+            assert_eq!(value(&config), value(&Config::default()), "{:?}", format);
+        }
+    }
+}
@@ -0,0 +1,860 @@
+// Config validation: every problem in one pass, each with a file, path and position
+//
+// `Config::load` only needs serde to succeed, which silently ignores unknown
+// keys and accepts any string for enum-like fields. This walks the merged raw
+// config against the known layout instead and reports everything it finds.
+
+use super::expand::expand;
+use super::layers::{self, join, Layer, Merged, Origin};
+use super::{Config, ConfigFormat, LayoutType, Scheme, SourceKind};
+use crate::theme::{self, Color};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The config file the problem is in, if it can be pinned to one
+    pub file: Option<PathBuf>,
+    /// Where in the config, e.g. `actions[2].keybind`
+    pub path: String,
+    pub message: String,
+    /// Line and column in the file, both starting at 1
+    pub location: Option<(usize, usize)>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+            if let Some((line, column)) = self.location {
+                write!(f, "{}:{}:", line, column)?;
+            }
+            f.write_str(" ")?;
+        }
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let path = if self.path.is_empty() { "config" } else { &self.path };
+        write!(f, "{}: {}: {}", severity, path, self.message)
+    }
+}
+
+/// Known keys per section, and whether each one is required; everything
+/// outside of actions has a default
+const CONFIG_FIELDS: &[(&str, bool)] = &[
+    ("theme", false),
+    ("layout", false),
+    ("effects", false),
+    ("actions", false),
+];
+
+const THEME_FIELDS: &[(&str, bool)] = &[
+    ("source", false),
+    ("manual_colors", false),
+    ("file_path", false),
+    ("command", false),
+    ("command_timeout", false),
+    ("seed", false),
+    ("preset", false),
+    ("wallpaper", false),
+    ("wallpaper_command", false),
+    ("watch_file", false),
+    ("scheme", false),
+    ("pywal_mapping", false),
+    ("base16_mapping", false),
+    ("terminal_mapping", false),
+    ("min_contrast", false),
+    ("auto_contrast", false),
+    ("high_contrast", false),
+    ("css_template", false),
+    ("user_stylesheet", false),
+];
+
+const MANUAL_COLOR_FIELDS: &[(&str, bool)] = &[
+    ("background", false),
+    ("primary", false),
+    ("secondary", false),
+    ("text", false),
+    ("danger", false),
+    ("surface", false),
+    ("surface_variant", false),
+    ("outline", false),
+    ("on_primary", false),
+    ("on_danger", false),
+    ("focus", false),
+    ("dialog_background", false),
+    ("shadow", false),
+];
+
+const LAYOUT_FIELDS: &[(&str, bool)] = &[
+    ("layout_type", false),
+    ("button_size", false),
+    ("button_spacing", false),
+    ("margin", false),
+    ("columns", false),
+];
+
+const EFFECTS_FIELDS: &[(&str, bool)] = &[
+    ("blur", false),
+    ("animations", false),
+    ("hover_effects", false),
+    ("transition_duration", false),
+];
+
+const ACTION_FIELDS: &[(&str, bool)] = &[
+    ("name", true),
+    ("command", true),
+    ("icon", true),
+    ("keybind", false),
+    ("confirm", false),
+    ("danger", false),
+];
+
+/// Read, check and merge the config files, lowest precedence first.
+///
+/// Each file is parsed on its own, so syntax errors point into the file that
+/// has them; everything else is checked on the merged config and points at
+/// the file that set the value, with its line and column. The merged config
+/// is `None` if a file couldn't be read or parsed.
+pub fn validate(files: &[PathBuf]) -> (Option<Merged>, Vec<Diagnostic>) {
+    let mut layers = Vec::new();
+    let mut typed_errors = Vec::new();
+    let mut diagnostics = Vec::new();
+    for file in files {
+        let content = match std::fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(e) => {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    file: Some(file.clone()),
+                    path: String::new(),
+                    message: format!("cannot read {}: {}", file.display(), e),
+                    location: None,
+                });
+                continue;
+            }
+        };
+        let format = ConfigFormat::from_path(file);
+        let value = match parse::<Value>(&content, format) {
+            Ok(value) => value,
+            Err((message, location)) => {
+                diagnostics.push(Diagnostic { severity: Severity::Error, file: Some(file.clone()), path: String::new(), message, location });
+                continue;
+            }
+        };
+        if let Err((message, location)) = parse::<Config>(&content, format) {
+            typed_errors.push((message, Origin { file: file.clone(), location }));
+        }
+        let locations = match format {
+            ConfigFormat::Json => locate(&content),
+            ConfigFormat::Toml => locate_toml(&content),
+            ConfigFormat::Yaml => locate_yaml(&content),
+        };
+        layers.push(Layer { file: file.clone(), value, locations });
+    }
+    if !diagnostics.is_empty() {
+        return (None, diagnostics);
+    }
+
+    let merged = layers::merge(layers);
+    let mut validator = Validator { origins: &merged.origins, diagnostics: Vec::new() };
+    validator.config(&merged.value);
+
+    // Type mismatches the walk doesn't look at, such as a string button_size.
+    // The file that has the same problem on its own is the one to point at.
+    if validator.errors() == 0 {
+        if let Err(e) = serde_json::from_value::<Config>(merged.value.clone()) {
+            let message = e.to_string();
+            let origin = typed_errors.iter().find(|(other, _)| *other == message).map(|(_, origin)| origin);
+            validator.diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                file: origin.map(|origin| origin.file.clone()),
+                path: String::new(),
+                message,
+                location: origin.and_then(|origin| origin.location),
+            });
+        }
+    }
+
+    let mut diagnostics = validator.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| (files.iter().position(|file| Some(file) == diagnostic.file.as_ref()), diagnostic.location));
+    (Some(merged), diagnostics)
+}
+
+/// Deserialize with the error's message and position kept apart
+fn parse<T: DeserializeOwned>(content: &str, format: ConfigFormat) -> Result<T, (String, Option<(usize, usize)>)> {
+    match format {
+        ConfigFormat::Json => serde_json::from_str(content)
+            .map_err(|e| (strip_position(&e.to_string()), Some((e.line(), e.column())))),
+        ConfigFormat::Toml => toml::from_str(content)
+            .map_err(|e| (e.message().trim().replace('\n', ", "), e.span().map(|span| position(content, span.start)))),
+        ConfigFormat::Yaml => serde_yaml::from_str(content)
+            .map_err(|e| (strip_position(&e.to_string()), e.location().map(|location| (location.line(), location.column())))),
+    }
+}
+
+/// Line and column of a byte offset
+fn position(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rfind('\n').map_or(before.len(), |newline| before.len() - newline - 1) + 1;
+    (line, column)
+}
+
+/// serde_json and serde_yaml append " at line N column M"; we report the position separately
+fn strip_position(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message.to_string(),
+    }
+}
+
+struct Validator<'a> {
+    origins: &'a HashMap<String, Origin>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    fn report(&mut self, severity: Severity, path: &str, message: String) {
+        // Point at the closest enclosing key we know the origin of
+        let mut lookup = path;
+        let origin = loop {
+            if let Some(origin) = self.origins.get(lookup) {
+                break Some(origin);
+            }
+            match lookup.rfind(['.', '[']) {
+                Some(index) => lookup = &lookup[..index],
+                None => break None,
+            }
+        };
+        self.diagnostics.push(Diagnostic {
+            severity,
+            file: origin.map(|origin| origin.file.clone()),
+            path: path.to_string(),
+            message,
+            location: origin.and_then(|origin| origin.location),
+        });
+    }
+
+    fn error(&mut self, path: &str, message: String) {
+        self.report(Severity::Error, path, message);
+    }
+
+    fn warning(&mut self, path: &str, message: String) {
+        self.report(Severity::Warning, path, message);
+    }
+
+    fn errors(&self) -> usize {
+        self.diagnostics.iter().filter(|diagnostic| diagnostic.severity == Severity::Error).count()
+    }
+
+    /// Check an object's keys against `fields`
+    fn object<'a>(&mut self, path: &str, value: &'a Value, fields: &[(&str, bool)]) -> Option<&'a Map<String, Value>> {
+        let Some(object) = value.as_object() else {
+            self.error(path, "expected an object".to_string());
+            return None;
+        };
+
+        for key in object.keys() {
+            if fields.iter().any(|(name, _)| name == key) {
+                continue;
+            }
+            let message = match closest(key, fields.iter().map(|(name, _)| *name)) {
+                Some(suggestion) => format!("unknown key '{}', did you mean '{}'?", key, suggestion),
+                None => format!("unknown key '{}'", key),
+            };
+            self.error(&join(path, key), message);
+        }
+        for (name, required) in fields {
+            if *required && !object.contains_key(*name) {
+                self.error(path, format!("missing required key '{}'", name));
+            }
+        }
+        Some(object)
+    }
+
+    /// Check that an optional string field is one of `allowed`
+    fn one_of(&mut self, path: &str, value: Option<&Value>, allowed: &[&str]) {
+        let Some(value) = value.filter(|value| !value.is_null()) else {
+            return;
+        };
+        let Some(text) = value.as_str() else {
+            self.error(path, "expected a string".to_string());
+            return;
+        };
+        if allowed.contains(&text) {
+            return;
+        }
+        let message = match closest(text, allowed.iter().copied()) {
+            Some(suggestion) => format!("invalid value '{}', did you mean '{}'?", text, suggestion),
+            None => format!("invalid value '{}', expected one of: {}", text, allowed.join(", ")),
+        };
+        self.error(path, message);
+    }
+
+    fn color(&mut self, path: &str, value: Option<&Value>) {
+        if let Some(text) = value.and_then(Value::as_str) {
+            if let Err(e) = Color::parse(text) {
+                self.error(path, format!("invalid color '{}': {}", text, e));
+            }
+        }
+    }
+
+    /// Warn about a configured file that doesn't exist once `~` and
+    /// environment variables are expanded
+    fn file(&mut self, path: &str, value: Option<&Value>) {
+        let Some(file) = value.and_then(Value::as_str) else {
+            return;
+        };
+        match expand(file) {
+            Ok(expanded) if !Path::new(&expanded).exists() => {
+                self.warning(path, format!("file not found: {}", expanded));
+            }
+            Ok(_) => {}
+            Err(e) => self.warning(path, e),
+        }
+    }
+
+    fn config(&mut self, root: &Value) {
+        let Some(config) = self.object("", root, CONFIG_FIELDS) else {
+            return;
+        };
+        if let Some(theme) = config.get("theme") {
+            self.theme(theme);
+        }
+        if let Some(layout) = config.get("layout") {
+            if let Some(layout) = self.object("layout", layout, LAYOUT_FIELDS) {
+                let layout_types: Vec<&str> = LayoutType::ALL.iter().map(|layout| layout.as_str()).collect();
+                self.one_of("layout.layout_type", layout.get("layout_type"), &layout_types);
+            }
+        }
+        if let Some(effects) = config.get("effects") {
+            self.object("effects", effects, EFFECTS_FIELDS);
+        }
+        if let Some(actions) = config.get("actions") {
+            self.actions(actions);
+        }
+    }
+
+    fn theme(&mut self, value: &Value) {
+        let Some(theme) = self.object("theme", value, THEME_FIELDS) else {
+            return;
+        };
+
+        let sources: Vec<(String, &Value)> = match theme.get("source") {
+            Some(Value::Array(sources)) => sources.iter().enumerate()
+                .map(|(index, source)| (format!("theme.source[{}]", index), source))
+                .collect(),
+            Some(source) => vec![("theme.source".to_string(), source)],
+            None => Vec::new(),
+        };
+        let source_names: Vec<&str> = theme::source_names().collect();
+        for (path, source) in &sources {
+            self.one_of(path, Some(source), &source_names);
+        }
+        let uses = |name: &str| sources.iter().any(|(_, source)| source.as_str() == Some(name));
+
+        let schemes: Vec<&str> = Scheme::ALL.iter().map(|scheme| scheme.as_str()).collect();
+        self.one_of("theme.scheme", theme.get("scheme"), &schemes);
+        // Presets are looked up case-insensitively
+        let preset = theme.get("preset")
+            .filter(|preset| !preset.as_str().is_some_and(|name| theme::preset_names().any(|known| known.eq_ignore_ascii_case(name))));
+        self.one_of("theme.preset", preset, &theme::preset_names().collect::<Vec<_>>());
+        self.color("theme.seed", theme.get("seed"));
+
+        if let Some(colors) = theme.get("manual_colors").filter(|colors| !colors.is_null()) {
+            if let Some(colors) = self.object("theme.manual_colors", colors, MANUAL_COLOR_FIELDS) {
+                for (role, value) in colors {
+                    self.color(&join("theme.manual_colors", role), Some(value));
+                }
+            }
+        }
+
+        // Settings the configured sources can't do without
+        let unset = |key: &str| theme.get(key).is_none_or(Value::is_null);
+        let needs = [
+            ("file", "file_path"),
+            ("base16", "file_path"),
+            ("command", "command"),
+            ("seed", "seed"),
+            ("preset", "preset"),
+        ];
+        for (source, key) in needs {
+            if uses(source) && unset(key) {
+                self.unusable_source(sources.len(), format!("the '{}' source needs theme.{} to be set", source, key));
+            }
+        }
+        if uses("wallpaper") && unset("wallpaper") && unset("wallpaper_command") {
+            self.unusable_source(
+                sources.len(),
+                "the 'wallpaper' source needs theme.wallpaper or theme.wallpaper_command to be set".to_string(),
+            );
+        }
+
+        let reads_file = |source: &Value| {
+            source.as_str().and_then(|name| name.parse::<SourceKind>().ok()).is_some_and(theme::reads_file)
+        };
+        if sources.iter().any(|(_, source)| reads_file(source))
+            || theme.get("watch_file").and_then(Value::as_bool) == Some(true)
+        {
+            self.file("theme.file_path", theme.get("file_path"));
+        }
+        if uses("wallpaper") {
+            self.file("theme.wallpaper", theme.get("wallpaper"));
+        }
+        self.file("theme.css_template", theme.get("css_template"));
+        self.file("theme.user_stylesheet", theme.get("user_stylesheet"));
+    }
+
+    /// A source that can never work is an error on its own, but in a chain
+    /// it is just skipped
+    fn unusable_source(&mut self, chain_length: usize, message: String) {
+        if chain_length > 1 {
+            self.warning("theme.source", message);
+        } else {
+            self.error("theme.source", message);
+        }
+    }
+
+    fn actions(&mut self, value: &Value) {
+        let Some(actions) = value.as_array() else {
+            self.error("actions", "expected a list of actions".to_string());
+            return;
+        };
+
+        let mut keybinds: HashMap<String, usize> = HashMap::new();
+        for (index, action) in actions.iter().enumerate() {
+            let path = format!("actions[{}]", index);
+            let Some(action) = self.object(&path, action, ACTION_FIELDS) else {
+                continue;
+            };
+
+            if let Some(keybind) = action.get("keybind").and_then(Value::as_str) {
+                // Keys are matched case-insensitively
+                match keybinds.get(&keybind.to_lowercase()) {
+                    Some(first) => self.error(
+                        &format!("{}.keybind", path),
+                        format!("keybind '{}' is already used by actions[{}]", keybind, first),
+                    ),
+                    None => {
+                        keybinds.insert(keybind.to_lowercase(), index);
+                    }
+                }
+            }
+
+            // Same rule as the UI: paths and file names are files, anything else an icon
+            // name. A variable that can't be expanded only makes sense in a path.
+            if let Some(icon) = action.get("icon").and_then(Value::as_str) {
+                let icon = expand(icon).unwrap_or_else(|_| icon.to_string());
+                if icon.starts_with('/') || icon.contains('.') || icon.contains('$') {
+                    self.file(&format!("{}.icon", path), action.get("icon"));
+                }
+            }
+        }
+    }
+}
+
+/// The candidate within two edits of `word`, to suggest for typos
+fn closest<'a>(word: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (edit_distance(&word.to_lowercase(), candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Positions of TOML keys and tables, by path.
+///
+/// Covers what a config uses: `[table]` and `[[array]]` headers and
+/// `key = value` lines. Values spanning several lines aren't looked into.
+fn locate_toml(content: &str) -> HashMap<String, (usize, usize)> {
+    let mut locations = HashMap::new();
+    let mut table = String::new();
+    let mut array_lengths: HashMap<String, usize> = HashMap::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let position = (index + 1, line.len() - trimmed.len() + 1);
+        if trimmed.starts_with('#') {
+            continue;
+        }
+
+        if let Some(header) = trimmed.strip_prefix("[[") {
+            let name = toml_key(header.split("]]").next().unwrap_or_default());
+            let length = array_lengths.entry(name.clone()).or_insert(0);
+            table = format!("{}[{}]", name, length);
+            *length += 1;
+            locations.insert(table.clone(), position);
+        } else if let Some(header) = trimmed.strip_prefix('[') {
+            table = toml_key(header.split(']').next().unwrap_or_default());
+            locations.insert(table.clone(), position);
+        } else if let Some((key, _)) = trimmed.split_once('=') {
+            locations.insert(join(&table, &toml_key(key)), position);
+        }
+    }
+    locations
+}
+
+/// A TOML key as a path: `a . "b" . 'c'` is `a.b.c`
+fn toml_key(key: &str) -> String {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut quote = None;
+    for c in key.chars() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            (None, '.') => parts.push(std::mem::take(&mut part)),
+            (None, c) if c.is_whitespace() => {}
+            (_, c) => part.push(c),
+        }
+    }
+    parts.push(part);
+    parts.join(".")
+}
+
+/// Positions of YAML keys and list items, by path.
+///
+/// Covers block style, which is what a config uses: `key: value`, nested
+/// by indentation, and `- ` items. Flow style (`{ a: 1 }`, `[a, b]`) is one
+/// value, and block scalars (`|`, `>`) are skipped.
+fn locate_yaml(content: &str) -> HashMap<String, (usize, usize)> {
+    let mut locations = HashMap::new();
+    // Open keys and items, innermost last: their column, path and whether it's an item
+    let mut parents: Vec<(usize, String, bool)> = Vec::new();
+    let mut list_lengths: HashMap<String, usize> = HashMap::new();
+    // Lines indented past this column belong to a block scalar
+    let mut block_scalar = None;
+
+    for (index, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let mut column = line.len() - trimmed.len();
+        if trimmed.is_empty() || trimmed.starts_with('#') || block_scalar.is_some_and(|block| column > block) {
+            continue;
+        }
+        block_scalar = None;
+        if trimmed.starts_with("---") || trimmed.starts_with("...") {
+            parents.clear();
+            continue;
+        }
+
+        let mut rest = trimmed;
+        // `- ` items, possibly nested on one line, then perhaps a key
+        while let Some(item) = rest.strip_prefix("- ").or_else(|| (rest == "-").then_some("")) {
+            // A list can be indented as far as its key, but not a sibling item
+            while parents.last().is_some_and(|&(open, _, is_item)| open > column || (open == column && is_item)) {
+                parents.pop();
+            }
+            let list = parents.last().map(|(_, path, _)| path.clone()).unwrap_or_default();
+            let length = list_lengths.entry(list.clone()).or_insert(0);
+            let path = format!("{}[{}]", list, length);
+            *length += 1;
+            locations.insert(path.clone(), (index + 1, column + 1));
+            parents.push((column, path, true));
+
+            let content = item.trim_start();
+            column += rest.len() - content.len();
+            rest = content;
+        }
+
+        let Some((key, value)) = yaml_key(rest) else {
+            continue;
+        };
+        while parents.last().is_some_and(|&(open, _, _)| open >= column) {
+            parents.pop();
+        }
+        let path = join(parents.last().map_or("", |(_, path, _)| path.as_str()), &key);
+        locations.insert(path.clone(), (index + 1, column + 1));
+        if value.starts_with(['|', '>']) {
+            block_scalar = Some(column);
+        }
+        parents.push((column, path, false));
+    }
+    locations
+}
+
+/// The key of a `key: value` line, unquoted, and the value
+fn yaml_key(line: &str) -> Option<(String, &str)> {
+    let (key, rest) = match line.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let end = line[1..].find(quote)? + 1;
+            (line[1..end].to_string(), line[end + 1..].trim_start())
+        }
+        _ => {
+            let end = line.find(": ").or_else(|| line.ends_with(':').then(|| line.len() - 1))?;
+            (line[..end].trim_end().to_string(), &line[end..])
+        }
+    };
+    let value = rest.strip_prefix(':')?;
+    (value.is_empty() || value.starts_with(' ')).then(|| (key, value.trim()))
+}
+
+/// Positions of every object key and array element, by path.
+///
+/// Only run on text serde_json already accepted, so it doesn't validate.
+fn locate(content: &str) -> HashMap<String, (usize, usize)> {
+    let mut scanner = Scanner { chars: content.chars().peekable(), line: 1, column: 1, locations: HashMap::new() };
+    scanner.value("");
+    scanner.locations
+}
+
+struct Scanner<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+    locations: HashMap<String, (usize, usize)>,
+}
+
+impl Scanner<'_> {
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    fn value(&mut self, path: &str) {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => self.object(path),
+            Some('[') => self.array(path),
+            Some('"') => {
+                self.string();
+            }
+            Some(_) => {
+                // Number, bool or null
+                while self.chars.peek().is_some_and(|c| !matches!(c, ',' | ']' | '}') && !c.is_whitespace()) {
+                    self.bump();
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn object(&mut self, path: &str) {
+        self.bump();
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some('}') | None => {
+                    self.bump();
+                    return;
+                }
+                Some('"') => {
+                    let position = (self.line, self.column);
+                    let child = join(path, &self.string());
+                    self.skip_whitespace();
+                    self.bump(); // ':'
+                    self.locations.insert(child.clone(), position);
+                    self.value(&child);
+                }
+                Some(_) => {
+                    self.bump(); // ','
+                }
+            }
+        }
+    }
+
+    fn array(&mut self, path: &str) {
+        self.bump();
+        let mut index = 0;
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some(']') | None => {
+                    self.bump();
+                    return;
+                }
+                Some(',') => {
+                    self.bump();
+                }
+                Some(_) => {
+                    let child = format!("{}[{}]", path, index);
+                    self.locations.insert(child.clone(), (self.line, self.column));
+                    self.value(&child);
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> String {
+        self.bump();
+        let mut text = String::new();
+        while let Some(c) = self.bump() {
+            match c {
+                '"' => break,
+                '\\' => {
+                    // Keys with escapes are rare enough that the raw escape will do
+                    if let Some(escaped) = self.bump() {
+                        text.push(escaped);
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(locations: &HashMap<String, (usize, usize)>, path: &str) -> Option<(usize, usize)> {
+        locations.get(path).copied()
+    }
+
+    #[test]
+    fn locates_json_keys_and_array_elements() {
+        let locations = locate(r#"{
+  "theme": {
+    "source": ["file", "manual"],
+    "terminal_mapping": { "say \"hi\"": "color4" }
+  },
+  "actions": [
+    { "name": "Lock", "command": "hyprlock" },
+    {
+      "name": "Logout",
+      "confirm": true
+    }
+  ]
+}"#);
+        assert_eq!(at(&locations, "theme"), Some((2, 3)));
+        assert_eq!(at(&locations, "theme.source"), Some((3, 5)));
+        assert_eq!(at(&locations, "theme.source[1]"), Some((3, 24)));
+        assert_eq!(at(&locations, "theme.terminal_mapping.say \"hi\""), Some((4, 27)));
+        assert_eq!(at(&locations, "actions[0]"), Some((7, 5)));
+        assert_eq!(at(&locations, "actions[0].command"), Some((7, 23)));
+        assert_eq!(at(&locations, "actions[1]"), Some((8, 5)));
+        assert_eq!(at(&locations, "actions[1].confirm"), Some((10, 7)));
+        assert_eq!(at(&locations, "actions[2]"), None);
+    }
+
+    #[test]
+    fn locates_toml_tables_keys_and_array_tables() {
+        let locations = locate_toml(r##"# comment = not a key
+[theme]
+source = ["file", "manual"]
+"scheme" = "dark"
+
+[theme."manual_colors"]
+primary = "#ffffff"
+
+[[actions]]
+name = "Lock"
+
+[[ actions ]]
+  name = "Logout"
+  'command' = "loginctl terminate-session self"
+"##);
+        assert_eq!(at(&locations, "comment"), None);
+        assert_eq!(at(&locations, "theme"), Some((2, 1)));
+        assert_eq!(at(&locations, "theme.source"), Some((3, 1)));
+        assert_eq!(at(&locations, "theme.scheme"), Some((4, 1)));
+        assert_eq!(at(&locations, "theme.manual_colors"), Some((6, 1)));
+        assert_eq!(at(&locations, "theme.manual_colors.primary"), Some((7, 1)));
+        assert_eq!(at(&locations, "actions[0]"), Some((9, 1)));
+        assert_eq!(at(&locations, "actions[0].name"), Some((10, 1)));
+        assert_eq!(at(&locations, "actions[1]"), Some((12, 1)));
+        assert_eq!(at(&locations, "actions[1].name"), Some((13, 3)));
+        assert_eq!(at(&locations, "actions[1].command"), Some((14, 3)));
+    }
+
+    #[test]
+    fn locates_yaml_keys_and_list_items() {
+        let locations = locate_yaml(r##"# comment: not a key
+theme:
+  source:
+    - file
+    - manual
+  "scheme": dark
+  manual_colors:
+    primary: "#89b4fa"
+  command: |
+    echo "background: #000000"
+layout: { layout_type: grid }
+actions:
+- name: Lock
+  command: swaylock
+-   name: Logout
+    confirm: true
+    icon: 'https://example.com/logout.svg'
+"##);
+        assert_eq!(at(&locations, "comment"), None);
+        assert_eq!(at(&locations, "theme"), Some((2, 1)));
+        assert_eq!(at(&locations, "theme.source"), Some((3, 3)));
+        assert_eq!(at(&locations, "theme.source[0]"), Some((4, 5)));
+        assert_eq!(at(&locations, "theme.source[1]"), Some((5, 5)));
+        assert_eq!(at(&locations, "theme.scheme"), Some((6, 3)));
+        assert_eq!(at(&locations, "theme.manual_colors.primary"), Some((8, 5)));
+        assert_eq!(at(&locations, "theme.command"), Some((9, 3)));
+        assert_eq!(at(&locations, "theme.command.background"), None);
+        assert_eq!(at(&locations, "layout"), Some((11, 1)));
+        assert_eq!(at(&locations, "actions"), Some((12, 1)));
+        assert_eq!(at(&locations, "actions[0]"), Some((13, 1)));
+        assert_eq!(at(&locations, "actions[0].name"), Some((13, 3)));
+        assert_eq!(at(&locations, "actions[0].command"), Some((14, 3)));
+        assert_eq!(at(&locations, "actions[1]"), Some((15, 1)));
+        assert_eq!(at(&locations, "actions[1].name"), Some((15, 5)));
+        assert_eq!(at(&locations, "actions[1].confirm"), Some((16, 5)));
+        assert_eq!(at(&locations, "actions[1].icon"), Some((17, 5)));
+        assert_eq!(at(&locations, "actions[2]"), None);
+    }
+
+    #[test]
+    fn yaml_diagnostics_have_positions() {
+        let dir = std::env::temp_dir().join(format!("departure-validate-yaml-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("config.yaml");
+        std::fs::write(&file, "theme:\n  scheme: dusk\n").unwrap();
+        let (_, diagnostics) = validate(std::slice::from_ref(&file));
+        std::fs::remove_dir_all(&dir).unwrap();
+        let diagnostic = diagnostics.iter().find(|diagnostic| diagnostic.path == "theme.scheme").unwrap();
+        assert_eq!(diagnostic.location, Some((2, 3)));
+    }
+
+    #[test]
+    fn toml_keys_drop_quotes_and_spaces() {
+        assert_eq!(toml_key(" theme . \"manual_colors\" "), "theme.manual_colors");
+        assert_eq!(toml_key("'a.b'.c"), "a.b.c");
+    }
+}
@@ -1,14 +1,16 @@
 use gtk4::prelude::*;
 
-use clap::Parser;
+use clap::{ArgGroup, Parser};
 use std::path::PathBuf;
 use anyhow::Result;
 
 mod config;
+mod print;
 mod theme;
 mod ui;
 
-use config::Config;
+use config::{Config, ConfigFormat, SourceKind, ThemeSource};
+use print::Format;
 use theme::ThemeManager;
 use ui::DepartureApp;
 
@@ -16,6 +18,8 @@ use ui::DepartureApp;
 #[command(name = "departure")]
 #[command(about = "A flexible logout application for Wayland with Material You theming support")]
 #[command(version = "0.1.0")]
+#[command(group(ArgGroup::new("formatted").args(["print_theme", "print_config", "generate_config"]).multiple(true)))]
+#[command(group(ArgGroup::new("writing_config").args(["generate_config", "print_config"]).multiple(true)))]
 struct Cli {
     /// Path to configuration file
     #[arg(short, long)]
@@ -25,17 +29,47 @@ struct Cli {
     #[arg(long)]
     generate_config: bool,
     
+    /// Validate the configuration file and exit, non-zero if it has errors
+    #[arg(long)]
+    check_config: bool,
+
     /// Print current theme colors and exit
     #[arg(long)]
     print_theme: bool,
+
+    /// Print the effective config, merged from every config file, with the
+    /// file each value came from, and exit
+    #[arg(long)]
+    print_config: bool,
+
+    /// Output format: table, json, css or shell for --print-theme; table,
+    /// json, toml or yaml for --print-config; json, toml or yaml for
+    /// --generate-config (by default the --config extension, else json)
+    #[arg(long, value_enum, requires = "formatted")]
+    format: Option<Format>,
+
+    /// File format for --generate-config, the same as --format, or to print
+    /// the merged config as a single config file with --print-config
+    #[arg(long, value_enum, requires = "writing_config", conflicts_with = "format")]
+    config_format: Option<ConfigFormat>,
     
     /// Enable debug logging
     #[arg(short, long)]
     debug: bool,
     
-    /// Override theme source (manual, system, file, command)
+    /// Override theme source (manual, system, file, command, seed, wallpaper, preset,
+    /// base16, xresources, kitty, foot, alacritty);
+    /// separate several with commas to try them in order
     #[arg(long)]
-    theme_source: Option<String>,
+    theme_source: Option<ThemeSource>,
+
+    /// Use a built-in theme preset, e.g. nord (see --print-theme for the list)
+    #[arg(long, conflicts_with = "theme_source")]
+    theme: Option<String>,
+
+    /// Resolve the theme from scratch instead of using the cached one
+    #[arg(long)]
+    refresh_theme: bool,
 }
 
 fn main() -> Result<()> {
@@ -54,19 +88,27 @@ fn main() -> Result<()> {
     
     log::info!("Starting departure logout application");
     
-    // Determine config path
-    let config_path = cli.config.unwrap_or_else(|| {
-        let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
-        path.push("departure");
-        path.push("config.json");
-        path
-    });
-    
     // Generate config if requested
     if cli.generate_config {
-        return generate_default_config(&config_path);
+        let (path, format) = generated_config(&cli)?;
+        return generate_default_config(&path, format);
     }
+
+    // Determine config path: config.toml, config.yaml or config.json
+    let config_path = cli.config.unwrap_or_else(config::find_config);
     
+    if cli.check_config {
+        return check_config(&config_path);
+    }
+
+    if cli.print_config {
+        let layered = Config::load_layered(&config_path)?;
+        return match cli.config_format {
+            Some(format) => print::print_config_file(&layered, format),
+            None => print::print_config(&layered, cli.format.unwrap_or(Format::Table)),
+        };
+    }
+
     // Load configuration
     let mut config = Config::load(&config_path)?;
     
@@ -74,19 +116,29 @@ fn main() -> Result<()> {
     if let Some(source) = cli.theme_source {
         config.theme.source = source;
     }
+
+    // Use a built-in preset if requested
+    if let Some(preset) = cli.theme {
+        if !theme::preset_names().any(|name| name.eq_ignore_ascii_case(&preset)) {
+            anyhow::bail!(
+                "Unknown theme preset '{}' (available: {})",
+                preset,
+                theme::preset_names().collect::<Vec<_>>().join(", "),
+            );
+        }
+        config.theme.source = ThemeSource::Single(SourceKind::Preset);
+        config.theme.preset = Some(preset);
+    }
     
     // Initialize theme manager
-    let theme_manager = ThemeManager::new(config.theme.clone())?;
+    let mut theme_manager = ThemeManager::new(config.theme.clone(), config.effects.clone())?;
+    if cli.refresh_theme {
+        theme_manager.bypass_cache();
+    }
     
     // Print theme and exit if requested
     if cli.print_theme {
-        let colors = theme_manager.get_colors()?;
-        println!("Current theme colors:");
-        println!("  Background: {}", colors.background);
-        println!("  Primary: {}", colors.primary);
-        println!("  Secondary: {}", colors.secondary);
-        println!("  Text: {}", colors.text);
-        println!("  Danger: {}", colors.danger);
+        print::print_theme(&theme_manager, cli.format.unwrap_or(Format::Table))?;
         return Ok(());
     }
     
@@ -123,18 +175,104 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn generate_default_config(path: &PathBuf) -> Result<()> {
+fn check_config(path: &PathBuf) -> Result<()> {
+    // The system, drop-in and per-host files are checked along with `path`
+    let files = config::layers::files(path);
+    if files.is_empty() {
+        anyhow::bail!("No config file found at {}", path.display());
+    }
+    if !path.exists() {
+        println!("{} does not exist, checking the other config files", path.display());
+    }
+
+    let (_, diagnostics) = config::validate::validate(&files);
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+
+    let errors = diagnostics.iter()
+        .filter(|diagnostic| diagnostic.severity == config::validate::Severity::Error)
+        .count();
+    if errors > 0 {
+        anyhow::bail!("The config has {} error(s)", errors);
+    }
+    for file in &files {
+        println!("{} is valid", file.display());
+    }
+    Ok(())
+}
+
+/// Where --generate-config writes to, and in which format
+fn generated_config(cli: &Cli) -> Result<(PathBuf, ConfigFormat)> {
+    let format = match cli.format {
+        Some(format) => Some(format.config_format()
+            .ok_or_else(|| anyhow::anyhow!("--generate-config supports the json, toml and yaml formats"))?),
+        None => cli.config_format,
+    };
+    let path = cli.config.clone().unwrap_or_else(|| {
+        config::config_dir().join(format!("config.{}", format.unwrap_or(ConfigFormat::Json).extension()))
+    });
+    // Otherwise write what the file name says, so it loads again
+    let format = format.unwrap_or_else(|| ConfigFormat::from_path(&path));
+    Ok((path, format))
+}
+
+fn generate_default_config(path: &PathBuf, format: ConfigFormat) -> Result<()> {
     // Create parent directory if it doesn't exist
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)?;
     }
     
-    let default_config = Config::default();
-    let json = serde_json::to_string_pretty(&default_config)?;
-    std::fs::write(path, json)?;
+    // The TOML version is written by hand so it can explain every option
+    let content = match format {
+        ConfigFormat::Toml => config::DEFAULT_TOML.to_string(),
+        _ => format.serialize(&Config::default())?,
+    };
+    std::fs::write(path, content)?;
     
     println!("Generated default configuration at: {}", path.display());
     println!("Edit this file to customize your departure experience.");
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> clap::error::Result<Cli> {
+        Cli::try_parse_from(std::iter::once("departure").chain(args.iter().copied()))
+    }
+
+    fn generated(args: &[&str]) -> (PathBuf, ConfigFormat) {
+        generated_config(&parse(args).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn generate_config_takes_either_format_flag() {
+        assert_eq!(generated(&["--generate-config", "--format", "toml"]).1, ConfigFormat::Toml);
+        assert_eq!(generated(&["--generate-config", "--config-format", "toml"]).1, ConfigFormat::Toml);
+        assert_eq!(generated(&["--generate-config", "--format", "yaml"]).1, ConfigFormat::Yaml);
+        assert_eq!(generated(&["--generate-config"]).1, ConfigFormat::Json);
+        assert!(generated_config(&parse(&["--generate-config", "--format", "css"]).unwrap()).is_err());
+        assert!(parse(&["--generate-config", "--format", "toml", "--config-format", "toml"]).is_err());
+    }
+
+    #[test]
+    fn generate_config_follows_the_config_extension() {
+        let (path, format) = generated(&["--generate-config", "--config", "/tmp/departure.toml"]);
+        assert_eq!((path, format), (PathBuf::from("/tmp/departure.toml"), ConfigFormat::Toml));
+        assert_eq!(generated(&["--generate-config", "--config", "/tmp/departure.yml"]).1, ConfigFormat::Yaml);
+        assert_eq!(generated(&["--generate-config", "-c", "/tmp/departure.toml", "--format", "json"]).1, ConfigFormat::Json);
+    }
+
+    #[test]
+    fn formats_need_something_to_format() {
+        assert!(parse(&["--format", "json"]).is_err());
+        assert!(parse(&["--config-format", "toml"]).is_err());
+        assert!(parse(&["--config-format", "toml", "--print-theme"]).is_err());
+        assert!(parse(&["--print-theme", "--format", "shell"]).is_ok());
+        assert!(parse(&["--print-config", "--format", "yaml"]).is_ok());
+        assert!(parse(&["--print-config", "--config-format", "yaml"]).is_ok());
+    }
+}
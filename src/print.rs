@@ -0,0 +1,251 @@
+// --print-theme and --print-config output formats
+
+use crate::config::{ConfigFormat, Layered, SourceKind};
+use crate::theme::{self, Color, ColorOrigin, Resolution, ThemeManager};
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// For reading in a terminal: colors with truecolor swatches, or
+    /// config values with the file that set them
+    Table,
+    /// Everything the table shows, as JSON for scripts
+    Json,
+    /// The complete generated stylesheet
+    Css,
+    /// `export DEPARTURE_<ROLE>=...` lines to source from a shell
+    Shell,
+    /// A config file, for --generate-config (explaining every option) and
+    /// --print-config
+    Toml,
+    /// A config file, for --generate-config and --print-config
+    Yaml,
+}
+
+impl Format {
+    /// The config file format of the same name, if there is one
+    pub fn config_format(self) -> Option<ConfigFormat> {
+        match self {
+            Format::Json => Some(ConfigFormat::Json),
+            Format::Toml => Some(ConfigFormat::Toml),
+            Format::Yaml => Some(ConfigFormat::Yaml),
+            Format::Table | Format::Css | Format::Shell => None,
+        }
+    }
+}
+
+pub fn print_theme(manager: &ThemeManager, format: Format) -> Result<()> {
+    let print: fn(&ThemeManager, &Resolution) = match format {
+        Format::Table => print_table,
+        Format::Json => print_json,
+        Format::Css => print_css,
+        Format::Shell => print_shell,
+        Format::Toml | Format::Yaml => bail!("--print-theme supports the table, json, css and shell formats"),
+    };
+    print(manager, &manager.explain_colors());
+    Ok(())
+}
+
+/// Every role with its color and where it came from, semantic roles first
+fn colors(resolution: &Resolution) -> Vec<(String, Color, String)> {
+    let colors = &resolution.colors;
+    let source = resolution.source.map(SourceKind::as_str);
+    let semantic = colors.semantic_colors().into_iter()
+        .map(|(role, value)| (role.to_string(), value, origin_label(colors.origin(role), source)));
+    let material = colors.roles.iter()
+        .map(|(role, value)| (role.clone(), *value, origin_label(colors.material_origin(role), source)));
+    semantic.chain(material).collect()
+}
+
+/// E.g. "file: colors.primary.dark", or for a role the source didn't have,
+/// "derived, no surface_container in file"
+fn origin_label(origin: ColorOrigin, source: Option<&str>) -> String {
+    match (origin, source) {
+        (ColorOrigin::Source(Some(key)), Some(source)) => format!("{}: {}", source, key),
+        (ColorOrigin::Source(None), Some(source)) => source.to_string(),
+        (ColorOrigin::Derived(missing), Some(source)) if missing.is_empty() => format!("derived from {}", source),
+        (ColorOrigin::Derived(missing), Some(source)) => format!("derived, no {} in {}", missing.join(" or "), source),
+        (ColorOrigin::Default(missing), Some(source)) if !missing.is_empty() => {
+            format!("built-in, no {} in {}", missing.join(" or "), source)
+        }
+        (ColorOrigin::Contrast, _) => "adjusted for contrast".to_string(),
+        _ => "built-in".to_string(),
+    }
+}
+
+/// One line describing the source chain: which source won and why the others didn't
+fn source_summary(resolution: &Resolution) -> String {
+    let mut summary = match resolution.source {
+        Some(source) => format!("Theme source: {}", source),
+        None => "Theme source: built-in palette (no configured source worked)".to_string(),
+    };
+    for (source, reason) in &resolution.skipped {
+        summary.push_str(&format!("\n  skipped {}: {}", source, reason));
+    }
+    summary
+}
+
+fn print_table(manager: &ThemeManager, resolution: &Resolution) {
+    println!("{}", source_summary(resolution));
+    println!();
+
+    let colors = colors(resolution);
+    let width = colors.iter().map(|(role, _, _)| role.len()).max().unwrap_or(0);
+    for (role, value, origin) in &colors {
+        let swatch = format!("\x1b[48;2;{};{};{}m      \x1b[0m", value.r, value.g, value.b);
+        println!("  {} {:width$}  {:24} {}", swatch, role, value.to_string(), origin, width = width);
+    }
+    println!();
+
+    let issues = manager.contrast_issues(&resolution.colors);
+    if !issues.is_empty() {
+        println!("Contrast warnings:");
+        for issue in &issues {
+            println!(
+                "  {} on {}: {:.1}:1 (needs {:.1}:1)",
+                issue.foreground, issue.background, issue.ratio, issue.required,
+            );
+        }
+        println!();
+    }
+    println!("Available presets: {}", theme::preset_names().collect::<Vec<_>>().join(", "));
+}
+
+fn print_json(manager: &ThemeManager, resolution: &Resolution) {
+    let colors: serde_json::Map<String, serde_json::Value> = colors(resolution).into_iter()
+        .map(|(role, value, origin)| (role, json!({ "value": value.to_string(), "origin": origin })))
+        .collect();
+    let skipped: Vec<_> = resolution.skipped.iter()
+        .map(|(source, reason)| json!({ "source": source, "reason": reason }))
+        .collect();
+    let contrast: Vec<_> = manager.contrast_issues(&resolution.colors).iter()
+        .map(|issue| json!({
+            "foreground": issue.foreground,
+            "background": issue.background,
+            "ratio": (issue.ratio * 100.0).round() / 100.0,
+            "required": issue.required,
+        }))
+        .collect();
+
+    let output = json!({
+        "source": resolution.source,
+        "skipped": skipped,
+        "colors": colors,
+        "contrast_warnings": contrast,
+    });
+    println!("{}", serde_json::to_string_pretty(&output).expect("JSON values always serialize"));
+}
+
+fn print_css(manager: &ThemeManager, resolution: &Resolution) {
+    println!("/*");
+    for line in source_summary(resolution).lines() {
+        println!(" * {}", line);
+    }
+    for (role, _, origin) in colors(resolution) {
+        println!(" * {}: {}", role, origin);
+    }
+    println!(" */");
+    print!("{}", manager.generate_css(&resolution.colors));
+}
+
+fn print_shell(_manager: &ThemeManager, resolution: &Resolution) {
+    for line in source_summary(resolution).lines() {
+        println!("# {}", line.trim_start());
+    }
+    for (role, value, origin) in colors(resolution) {
+        println!("export DEPARTURE_{}='{}'  # {}", role.to_uppercase(), value, origin);
+    }
+}
+
+pub fn print_config(layered: &Layered, format: Format) -> Result<()> {
+    match format {
+        Format::Table => print_config_table(layered)?,
+        Format::Json => print_config_json(layered)?,
+        Format::Toml => print_config_file(layered, ConfigFormat::Toml)?,
+        Format::Yaml => print_config_file(layered, ConfigFormat::Yaml)?,
+        Format::Css | Format::Shell => bail!("--print-config supports the table, json, toml and yaml formats"),
+    }
+    Ok(())
+}
+
+/// The effective config as a single config file, without origins
+pub fn print_config_file(layered: &Layered, format: ConfigFormat) -> Result<()> {
+    // JSON has no comments; in TOML and YAML the output can be saved as is
+    if format != ConfigFormat::Json {
+        println!("# Merged from, lowest precedence first:");
+        for file in &layered.files {
+            println!("#   {}", file.display());
+        }
+    }
+    let output = format.serialize(&layered.config)?;
+    println!("{}", output.trim_end());
+    Ok(())
+}
+
+/// Every set value in the effective config by path, with the file and line
+/// that set it or "default"
+fn config_values(layered: &Layered) -> Result<Vec<(String, Value, String)>> {
+    let mut values = Vec::new();
+    flatten(String::new(), serde_json::to_value(&layered.config)?, &mut values);
+    Ok(values.into_iter()
+        .map(|(path, value)| {
+            let origin = layered.origins.get(&path).map_or_else(|| "default".to_string(), |origin| origin.to_string());
+            (path, value, origin)
+        })
+        .collect())
+}
+
+/// Tables are walked into and actions listed one by one; other lists are single values
+fn flatten(path: String, value: Value, values: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Null => {}
+        Value::Object(object) => {
+            for (key, value) in object {
+                let child = if path.is_empty() { key } else { format!("{}.{}", path, key) };
+                flatten(child, value, values);
+            }
+        }
+        Value::Array(actions) if path == "actions" => {
+            for (index, action) in actions.into_iter().enumerate() {
+                flatten(format!("actions[{}]", index), action, values);
+            }
+        }
+        value => values.push((path, value)),
+    }
+}
+
+fn print_config_table(layered: &Layered) -> Result<()> {
+    if layered.files.is_empty() {
+        println!("No config files found, using the defaults");
+    } else {
+        println!("Config files, lowest precedence first:");
+        for file in &layered.files {
+            println!("  {}", file.display());
+        }
+    }
+    println!();
+
+    let settings: Vec<(String, String)> = config_values(layered)?.into_iter()
+        .map(|(path, value, origin)| (format!("{} = {}", path, value), origin))
+        .collect();
+    let width = settings.iter().map(|(setting, _)| setting.len()).max().unwrap_or(0);
+    for (setting, origin) in &settings {
+        println!("  {:width$}  {}", setting, origin, width = width);
+    }
+    Ok(())
+}
+
+fn print_config_json(layered: &Layered) -> Result<()> {
+    let values: serde_json::Map<String, Value> = config_values(layered)?.into_iter()
+        .map(|(path, value, origin)| (path, json!({ "value": value, "origin": origin })))
+        .collect();
+    let output = json!({
+        "files": layered.files,
+        "config": layered.config,
+        "values": values,
+    });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
@@ -0,0 +1,43 @@
+// On-disk cache under $XDG_CACHE_HOME/departure
+
+use anyhow::{Result, anyhow};
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+pub fn cache_dir() -> Result<PathBuf> {
+    let mut dir = dirs::cache_dir().ok_or_else(|| anyhow!("Could not determine cache directory"))?;
+    dir.push("departure");
+    Ok(dir)
+}
+
+/// Read a cached JSON entry, treating a missing or unreadable entry as a miss
+pub fn read<T: DeserializeOwned>(name: &str) -> Option<T> {
+    let path = cache_dir().ok()?.join(name);
+    let content = std::fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            log::debug!("Ignoring corrupt cache entry {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+pub fn write<T: Serialize>(name: &str, value: &T) -> Result<()> {
+    let dir = cache_dir()?;
+    std::fs::create_dir_all(&dir)?;
+
+    // Write to a temporary file first so readers never see a partial entry
+    let path = dir.join(name);
+    let tmp_path = dir.join(format!("{}.tmp", name));
+    std::fs::write(&tmp_path, serde_json::to_string_pretty(value)?)?;
+    std::fs::rename(&tmp_path, &path)?;
+    Ok(())
+}
+
+/// Modification time in nanoseconds since the epoch, for cache keys
+pub fn mtime(path: &Path) -> Option<u128> {
+    let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
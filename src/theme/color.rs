@@ -0,0 +1,573 @@
+// Typed colors: parsing, validation and CSS serialization
+
+use anyhow::{Result, anyhow, bail};
+use std::fmt;
+use std::str::FromStr;
+
+/// An sRGB color with straight (non-premultiplied) alpha
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// Opacity in the range 0.0..=1.0
+    pub a: f64,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 1.0 }
+    }
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: f64) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Parse hex (`#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`), `rgb()`, `rgba()`,
+    /// `hsl()`, `hsla()` and CSS named colors.
+    pub fn parse(input: &str) -> Result<Self> {
+        let value = input.trim().to_ascii_lowercase();
+        if value.is_empty() {
+            bail!("empty color");
+        }
+
+        if let Some(hex) = value.strip_prefix('#') {
+            return Self::parse_hex(hex);
+        }
+
+        if let Some((function, args)) = value.strip_suffix(')').and_then(|v| v.split_once('(')) {
+            let args = split_args(args)?;
+            return match function.trim() {
+                "rgb" | "rgba" => Self::parse_rgb(&args),
+                "hsl" | "hsla" => Self::parse_hsl(&args),
+                other => Err(anyhow!("unsupported color function '{}()'", other)),
+            };
+        }
+
+        if let Some(color) = named_color(&value) {
+            return Ok(color);
+        }
+
+        // matugen's hex_stripped and some terminal configs omit the '#'
+        if matches!(value.len(), 6 | 8) && value.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Self::parse_hex(&value);
+        }
+
+        Err(anyhow!("unrecognized color '{}'", input.trim()))
+    }
+
+    fn parse_hex(hex: &str) -> Result<Self> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("invalid hex color '#{}'", hex);
+        }
+
+        let digit = |i: usize| u8::from_str_radix(&hex[i..=i], 16).map(|d| d * 17);
+        let pair = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+
+        let (r, g, b, a) = match hex.len() {
+            3 => (digit(0)?, digit(1)?, digit(2)?, 255),
+            4 => (digit(0)?, digit(1)?, digit(2)?, digit(3)?),
+            6 => (pair(0)?, pair(2)?, pair(4)?, 255),
+            8 => (pair(0)?, pair(2)?, pair(4)?, pair(6)?),
+            n => bail!("hex color '#{}' has {} digits, expected 3, 4, 6 or 8", hex, n),
+        };
+
+        Ok(Self::rgba(r, g, b, a as f64 / 255.0))
+    }
+
+    fn parse_rgb(args: &[&str]) -> Result<Self> {
+        if !matches!(args.len(), 3 | 4) {
+            bail!("rgb() takes 3 or 4 arguments, got {}", args.len());
+        }
+
+        let channel = |arg: &str| -> Result<u8> {
+            let value = match arg.strip_suffix('%') {
+                Some(percent) => parse_number(percent)? * 255.0 / 100.0,
+                None => parse_number(arg)?,
+            };
+            if !(0.0..=255.0).contains(&value) {
+                bail!("rgb component '{}' is out of range", arg);
+            }
+            Ok(value.round() as u8)
+        };
+
+        let alpha = args.get(3).map(|arg| parse_alpha(arg)).transpose()?.unwrap_or(1.0);
+        Ok(Self::rgba(channel(args[0])?, channel(args[1])?, channel(args[2])?, alpha))
+    }
+
+    fn parse_hsl(args: &[&str]) -> Result<Self> {
+        if !matches!(args.len(), 3 | 4) {
+            bail!("hsl() takes 3 or 4 arguments, got {}", args.len());
+        }
+
+        let hue = parse_hue(args[0])?;
+        let percentage = |arg: &str| -> Result<f64> {
+            let value = parse_number(arg.strip_suffix('%').unwrap_or(arg))?;
+            if !(0.0..=100.0).contains(&value) {
+                bail!("hsl component '{}' is out of range", arg);
+            }
+            Ok(value / 100.0)
+        };
+
+        let alpha = args.get(3).map(|arg| parse_alpha(arg)).transpose()?.unwrap_or(1.0);
+        Ok(Self::from_hsl(hue, percentage(args[1])?, percentage(args[2])?).with_alpha(alpha))
+    }
+
+    /// Build an opaque color from hue (degrees), saturation and lightness (0..=1)
+    pub fn from_hsl(hue: f64, saturation: f64, lightness: f64) -> Self {
+        let hue = hue.rem_euclid(360.0);
+        let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+        let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+        let m = lightness - chroma / 2.0;
+
+        let (r, g, b) = match hue {
+            h if h < 60.0 => (chroma, x, 0.0),
+            h if h < 120.0 => (x, chroma, 0.0),
+            h if h < 180.0 => (0.0, chroma, x),
+            h if h < 240.0 => (0.0, x, chroma),
+            h if h < 300.0 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+
+        let to_channel = |v: f64| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+        Self::rgb(to_channel(r), to_channel(g), to_channel(b))
+    }
+
+    /// Hue (degrees), saturation and lightness (0..=1); alpha is dropped
+    pub fn to_hsl(self) -> (f64, f64, f64) {
+        let [r, g, b] = [self.r, self.g, self.b].map(|c| c as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let lightness = (max + min) / 2.0;
+        let delta = max - min;
+        if delta == 0.0 {
+            return (0.0, 0.0, lightness);
+        }
+
+        let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+        let hue = if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        (hue, saturation, lightness)
+    }
+
+    /// Raise the HSL lightness by `amount` (0..=1), keeping hue and alpha
+    pub fn lighten(self, amount: f64) -> Self {
+        let (hue, saturation, lightness) = self.to_hsl();
+        Self::from_hsl(hue, saturation, (lightness + amount).clamp(0.0, 1.0)).with_alpha(self.a)
+    }
+
+    /// Lower the HSL lightness by `amount` (0..=1), keeping hue and alpha
+    pub fn darken(self, amount: f64) -> Self {
+        self.lighten(-amount)
+    }
+
+    /// Move `amount` (0..=1) of the way from this color towards `other`
+    pub fn mix(self, other: Color, amount: f64) -> Self {
+        let amount = amount.clamp(0.0, 1.0);
+        let channel = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * amount).round() as u8;
+        Self {
+            r: channel(self.r, other.r),
+            g: channel(self.g, other.g),
+            b: channel(self.b, other.b),
+            a: self.a + (other.a - self.a) * amount,
+        }
+    }
+
+    /// Composite this color over `backdrop` (source-over)
+    pub fn over(self, backdrop: Color) -> Self {
+        let alpha = self.a + backdrop.a * (1.0 - self.a);
+        if alpha <= 0.0 {
+            return Self::rgba(0, 0, 0, 0.0);
+        }
+        let channel = |fg: u8, bg: u8| {
+            ((fg as f64 * self.a + bg as f64 * backdrop.a * (1.0 - self.a)) / alpha).round() as u8
+        };
+        Self {
+            r: channel(self.r, backdrop.r),
+            g: channel(self.g, backdrop.g),
+            b: channel(self.b, backdrop.b),
+            a: alpha,
+        }
+    }
+
+    /// WCAG relative luminance of the opaque color
+    pub fn luminance(self) -> f64 {
+        let linear = |c: u8| {
+            let c = c as f64 / 255.0;
+            if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        };
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
+    }
+
+    /// WCAG contrast ratio between two colors, from 1.0 to 21.0
+    pub fn contrast(self, other: Color) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    pub fn with_alpha(self, alpha: f64) -> Self {
+        Self { a: alpha.clamp(0.0, 1.0), ..self }
+    }
+
+    pub fn is_opaque(self) -> bool {
+        self.a >= 1.0
+    }
+
+    /// `#rrggbb`, or `#rrggbbaa` for translucent colors
+    pub fn to_hex(self) -> String {
+        if self.is_opaque() {
+            format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        } else {
+            let alpha = (self.a * 255.0).round() as u8;
+            format!("#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, alpha)
+        }
+    }
+
+    /// Serialize for GTK CSS: hex when opaque, `rgba()` otherwise. The alpha
+    /// keeps every digit, so parsing the result gives back the same color.
+    pub fn to_css(self) -> String {
+        if self.is_opaque() {
+            self.to_hex()
+        } else {
+            format!("rgba({}, {}, {}, {})", self.r, self.g, self.b, self.a)
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_css())
+    }
+}
+
+/// Split function arguments on commas or whitespace, including the `/ alpha` form
+fn split_args(args: &str) -> Result<Vec<&str>> {
+    let parts: Vec<&str> = if args.contains(',') {
+        args.split(',').map(str::trim).collect()
+    } else {
+        args.split(|c: char| c.is_whitespace() || c == '/')
+            .filter(|part| !part.is_empty())
+            .collect()
+    };
+
+    if parts.iter().any(|part| part.is_empty()) {
+        bail!("empty argument in color function");
+    }
+    Ok(parts)
+}
+
+fn parse_number(value: &str) -> Result<f64> {
+    value.trim().parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .ok_or_else(|| anyhow!("'{}' is not a number", value.trim()))
+}
+
+/// A number in 0..=1, or a percentage
+pub(super) fn parse_alpha(value: &str) -> Result<f64> {
+    let alpha = match value.strip_suffix('%') {
+        Some(percent) => parse_number(percent)? / 100.0,
+        None => parse_number(value)?,
+    };
+    if !(0.0..=1.0).contains(&alpha) {
+        bail!("alpha '{}' is out of range", value);
+    }
+    Ok(alpha)
+}
+
+fn parse_hue(value: &str) -> Result<f64> {
+    if let Some(deg) = value.strip_suffix("deg") {
+        parse_number(deg)
+    } else if let Some(turn) = value.strip_suffix("turn") {
+        Ok(parse_number(turn)? * 360.0)
+    } else if let Some(rad) = value.strip_suffix("rad") {
+        Ok(parse_number(rad)?.to_degrees())
+    } else {
+        parse_number(value)
+    }
+}
+
+fn named_color(name: &str) -> Option<Color> {
+    if name == "transparent" {
+        return Some(Color::rgba(0, 0, 0, 0.0));
+    }
+
+    NAMED_COLORS.iter()
+        .find(|(candidate, _)| *candidate == name)
+        .map(|(_, rgb)| Color::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, *rgb as u8))
+}
+
+/// CSS Color Module Level 4 named colors
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(input: &str) -> Color {
+        Color::parse(input).unwrap_or_else(|e| panic!("{}: {}", input, e))
+    }
+
+    fn error(input: &str) -> String {
+        Color::parse(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn hex_with_3_4_6_and_8_digits() {
+        for (input, expected) in [
+            ("#fff", Color::rgb(255, 255, 255)),
+            ("#f008", Color::rgba(255, 0, 0, 0x88 as f64 / 255.0)),
+            ("#89b4fa", Color::rgb(0x89, 0xb4, 0xfa)),
+            ("#89B4FA80", Color::rgba(0x89, 0xb4, 0xfa, 0x80 as f64 / 255.0)),
+            // Without the '#', as matugen's hex_stripped writes them
+            ("89b4fa", Color::rgb(0x89, 0xb4, 0xfa)),
+            ("  #1e1e2e\n", Color::rgb(0x1e, 0x1e, 0x2e)),
+        ] {
+            assert_eq!(parsed(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn rgb_functions() {
+        for (input, expected) in [
+            ("rgb(137, 180, 250)", Color::rgb(137, 180, 250)),
+            ("rgb(100%, 0%, 50%)", Color::rgb(255, 0, 128)),
+            ("rgba(0, 0, 0, 0.8)", Color::rgba(0, 0, 0, 0.8)),
+            ("rgba(0, 0, 0, 25%)", Color::rgba(0, 0, 0, 0.25)),
+            ("rgb(137 180 250)", Color::rgb(137, 180, 250)),
+            ("rgb(137 180 250 / 0.5)", Color::rgba(137, 180, 250, 0.5)),
+            ("RGBA(137 180 250/50%)", Color::rgba(137, 180, 250, 0.5)),
+        ] {
+            assert_eq!(parsed(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn hsl_functions() {
+        for (input, expected) in [
+            ("hsl(0, 100%, 50%)", Color::rgb(255, 0, 0)),
+            ("hsl(120deg 100% 25%)", Color::rgb(0, 128, 0)),
+            ("hsl(0.5turn 100% 50%)", Color::rgb(0, 255, 255)),
+            ("hsl(-120, 100%, 50%)", Color::rgb(0, 0, 255)),
+            ("hsl(0 0% 100% / 20%)", Color::rgba(255, 255, 255, 0.2)),
+            ("hsla(240, 100%, 50%, 0.5)", Color::rgba(0, 0, 255, 0.5)),
+        ] {
+            assert_eq!(parsed(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn named_colors() {
+        assert_eq!(parsed("rebeccapurple"), Color::rgb(0x66, 0x33, 0x99));
+        assert_eq!(parsed("White"), Color::rgb(255, 255, 255));
+        assert_eq!(parsed("transparent"), Color::rgba(0, 0, 0, 0.0));
+    }
+
+    #[test]
+    fn malformed_colors_say_what_is_wrong() {
+        for (input, expected) in [
+            ("#89b4f", "hex color '#89b4f' has 5 digits, expected 3, 4, 6 or 8"),
+            ("#89b4fg", "invalid hex color '#89b4fg'"),
+            ("", "empty color"),
+            ("Blurple", "unrecognized color 'Blurple'"),
+            ("89b4f", "unrecognized color '89b4f'"),
+            ("rgb(1, 2)", "rgb() takes 3 or 4 arguments, got 2"),
+            ("rgb(256, 0, 0)", "rgb component '256' is out of range"),
+            ("rgb(0, 0, 0, 1.5)", "alpha '1.5' is out of range"),
+            ("rgb(x, 0, 0)", "'x' is not a number"),
+            ("rgb(1,, 2)", "empty argument in color function"),
+            ("hsl(0, 101%, 50%)", "hsl component '101%' is out of range"),
+            ("hsl(1, 2, 3, 4, 5)", "hsl() takes 3 or 4 arguments, got 5"),
+            ("cmyk(0, 0, 0, 0)", "unsupported color function 'cmyk()'"),
+        ] {
+            assert_eq!(error(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn to_string_parses_back_to_the_same_color() {
+        // The theme cache stores colors this way
+        for color in [
+            Color::rgb(0x89, 0xb4, 0xfa),
+            Color::rgba(36, 36, 36, 0.8),
+            Color::rgba(0x89, 0xb4, 0xfa, 0x80 as f64 / 255.0),
+            Color::rgba(0, 0, 0, 1.0 / 3.0),
+            Color::rgba(255, 255, 255, 0.0),
+            Color::rgb(0x1e, 0x1e, 0x2e).mix(Color::rgb(0xcd, 0xd6, 0xf4), 0.3).with_alpha(0.85 * 0.9),
+        ] {
+            assert_eq!(parsed(&color.to_string()), color, "{}", color);
+        }
+        assert_eq!(Color::rgb(0x89, 0xb4, 0xfa).to_string(), "#89b4fa");
+        assert_eq!(Color::rgba(36, 36, 36, 0.8).to_string(), "rgba(36, 36, 36, 0.8)");
+    }
+
+    #[test]
+    fn hsl_conversion_round_trips() {
+        for color in [Color::rgb(0x89, 0xb4, 0xfa), Color::rgb(0xf3, 0x8b, 0xa8), Color::rgb(128, 128, 128)] {
+            let (hue, saturation, lightness) = color.to_hsl();
+            assert_eq!(Color::from_hsl(hue, saturation, lightness), color);
+        }
+    }
+}
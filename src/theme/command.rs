@@ -0,0 +1,146 @@
+// Running user-supplied theme and wallpaper commands
+//
+// Commands run through `sh -c` with no stdin and a cap on how much they may
+// print. They keep departure's environment and working directory, since
+// tools such as gsettings, hyprctl and swaymsg find the session through it.
+// Each runs in its own process group, so a timeout takes down anything it
+// started, not just the shell.
+
+use anyhow::{Result, anyhow};
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::Duration;
+
+/// Most a command may print on stdout; colors and a wallpaper path are far less
+const MAX_OUTPUT: usize = 1024 * 1024;
+
+/// Run `command` and return its stdout, failing if it exits unsuccessfully,
+/// prints more than `MAX_OUTPUT` or is still running after `timeout`.
+pub fn run(command: &str, timeout: Duration) -> Result<String> {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start '{}': {}", command, e))?;
+    let pid = child.id();
+
+    // Collect the output on a helper thread so we can stop waiting for it
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(collect(child));
+    });
+
+    let output = match rx.recv_timeout(timeout) {
+        Ok(output) => output?,
+        Err(RecvTimeoutError::Timeout) => {
+            kill_group(pid);
+            return Err(anyhow!("'{}' timed out after {} ms", command, timeout.as_millis()));
+        }
+        Err(RecvTimeoutError::Disconnected) => return Err(anyhow!("Lost track of '{}'", command)),
+    };
+
+    if output.stdout.len() > MAX_OUTPUT {
+        return Err(anyhow!("'{}' printed more than {} KiB", command, MAX_OUTPUT / 1024));
+    }
+    if !output.status.success() {
+        return Err(anyhow!("'{}' failed: {}", command, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+struct Output {
+    status: ExitStatus,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+/// Read up to `MAX_OUTPUT` from the command and wait for it to exit. One
+/// byte more is read from stdout to tell when it printed too much.
+fn collect(mut child: Child) -> std::io::Result<Output> {
+    let pid = child.id();
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    let errors = std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = stderr.take(MAX_OUTPUT as u64).read_to_end(&mut buffer);
+        buffer
+    });
+    let mut buffer = Vec::new();
+    stdout.take(MAX_OUTPUT as u64 + 1).read_to_end(&mut buffer)?;
+    if buffer.len() > MAX_OUTPUT {
+        // Don't wait for a command that keeps printing
+        kill_group(pid);
+    }
+
+    let status = child.wait()?;
+    Ok(Output { status, stdout: buffer, stderr: errors.join().unwrap_or_default() })
+}
+
+/// Kill the command's process group; its pid is the group id
+fn kill_group(pid: u32) {
+    // SAFETY: killpg takes plain integers and only sends a signal
+    if unsafe { libc::killpg(pid as libc::pid_t, libc::SIGKILL) } == 0 {
+        return;
+    }
+    let error = std::io::Error::last_os_error();
+    // The whole group may have exited in the meantime
+    if error.raw_os_error() != Some(libc::ESRCH) {
+        log::warn!("Failed to kill timed out command (pid {}): {}", pid, error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn returns_stdout() {
+        assert_eq!(run("echo '#89b4fa'", TIMEOUT).unwrap(), "#89b4fa\n");
+    }
+
+    #[test]
+    fn failures_report_stderr() {
+        let error = run("echo broken >&2; exit 3", TIMEOUT).unwrap_err();
+        assert!(error.to_string().ends_with("failed: broken"), "{}", error);
+    }
+
+    #[test]
+    fn timeouts_kill_the_whole_group() {
+        let pid_file = std::env::temp_dir().join(format!("departure-command-{}", std::process::id()));
+        let command = format!("sleep 30 & echo $! > '{}'; wait", pid_file.display());
+        let error = run(&command, Duration::from_millis(500)).unwrap_err();
+        assert!(error.to_string().contains("timed out"), "{}", error);
+
+        let pid: u32 = std::fs::read_to_string(&pid_file).unwrap().trim().parse().unwrap();
+        std::fs::remove_file(&pid_file).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        // Gone, or a zombie waiting for init to reap it
+        let state = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+        let state = state.rsplit(") ").next().and_then(|rest| rest.chars().next());
+        assert!(matches!(state, None | Some('Z')), "the background sleep survived: {:?}", state);
+    }
+
+    #[test]
+    fn endless_output_is_cut_off() {
+        let error = run("yes", TIMEOUT).unwrap_err();
+        assert!(error.to_string().contains("printed more than"), "{}", error);
+    }
+
+    #[test]
+    fn session_environment_is_kept() {
+        // Stands in for DBUS_SESSION_BUS_ADDRESS, HYPRLAND_INSTANCE_SIGNATURE and the like
+        std::env::set_var("DEPARTURE_COMMAND_SESSION", "unix:path=/run/user/1000/bus");
+        assert_eq!(run("echo \"$DEPARTURE_COMMAND_SESSION\"", TIMEOUT).unwrap(), "unix:path=/run/user/1000/bus\n");
+        let directory = std::env::current_dir().unwrap();
+        assert_eq!(run("pwd -P", TIMEOUT).unwrap(), format!("{}\n", directory.canonicalize().unwrap().display()));
+    }
+}
@@ -0,0 +1,244 @@
+// WCAG contrast checks and automatic readable foregrounds
+
+use super::{ColorOrigin, ThemeColors};
+use super::color::Color;
+
+/// WCAG AA for normal text
+pub const AA_TEXT: f64 = 4.5;
+/// WCAG AAA for normal text
+pub const AAA_TEXT: f64 = 7.0;
+/// WCAG AA for borders, focus rings and other non-text UI (1.4.11)
+pub const AA_UI: f64 = 3.0;
+
+/// A foreground/background pair that doesn't reach its required ratio
+#[derive(Debug, Clone)]
+pub struct ContrastIssue {
+    pub foreground: &'static str,
+    pub background: &'static str,
+    pub ratio: f64,
+    pub required: f64,
+}
+
+/// Which kind of minimum a pair is held to
+#[derive(Clone, Copy)]
+enum Kind {
+    Text,
+    Ui,
+}
+
+/// Opacity of the button labels: 0.9 on the label inside a button drawn at
+/// 0.85 (base.css `.departure-button-text` and `.departure-button`)
+const LABEL_OPACITY: f64 = 0.85 * 0.9;
+
+/// How often `enforce` goes over the pairs before giving up
+const MAX_PASSES: usize = 4;
+
+/// Foreground/background role pairs as they are drawn by the built-in
+/// stylesheet, with the opacity the foreground is drawn at
+const PAIRS: &[(&str, &str, Kind, f64)] = &[
+    ("text", "background", Kind::Text, 1.0),
+    // Button labels, and the text of the confirmation buttons at full opacity
+    ("text", "surface", Kind::Text, LABEL_OPACITY),
+    // Hovered buttons
+    ("text", "surface_variant", Kind::Text, 1.0),
+    ("text", "dialog_background", Kind::Text, 1.0),
+    ("on_primary", "primary", Kind::Text, 1.0),
+    ("on_danger", "danger", Kind::Text, 1.0),
+    ("primary", "background", Kind::Ui, 1.0),
+    ("danger", "background", Kind::Ui, 1.0),
+    ("outline", "dialog_background", Kind::Ui, 1.0),
+    ("focus", "background", Kind::Ui, 1.0),
+];
+
+/// Required ratios for text and for UI components
+#[derive(Debug, Clone, Copy)]
+pub struct Requirements {
+    pub text: f64,
+    pub ui: f64,
+}
+
+impl Requirements {
+    fn for_kind(self, kind: Kind) -> f64 {
+        match kind {
+            Kind::Text => self.text,
+            Kind::Ui => self.ui,
+        }
+    }
+}
+
+/// List the pairs in `colors` that fall short of `requirements`.
+pub fn check(colors: &ThemeColors, requirements: Requirements) -> Vec<ContrastIssue> {
+    PAIRS.iter()
+        .filter_map(|&(foreground, background, kind, opacity)| {
+            let (fg, bg) = composited(colors, foreground, background, opacity);
+            let ratio = fg.contrast(bg);
+            let required = requirements.for_kind(kind);
+            (ratio < required).then_some(ContrastIssue { foreground, background, ratio, required })
+        })
+        .collect()
+}
+
+/// Adjust foregrounds until every pair meets `requirements`.
+///
+/// Text pairs only move text and `on_*` colors. UI pairs (borders, focus
+/// ring) are only fixed when `fix_ui` is set, since that changes accent colors.
+/// Fixing those moves `primary` and `danger`, the backgrounds of the `on_*`
+/// pairs, so the pairs are gone over again until nothing changes.
+pub fn enforce(colors: &mut ThemeColors, requirements: Requirements, fix_ui: bool) {
+    for _ in 0..MAX_PASSES {
+        let mut changed = false;
+        for &(foreground, background, kind, opacity) in PAIRS {
+            if matches!(kind, Kind::Ui) && !fix_ui {
+                continue;
+            }
+            let required = requirements.for_kind(kind);
+            let (shown, bg) = composited(colors, foreground, background, opacity);
+            if shown.contrast(bg) >= required {
+                continue;
+            }
+
+            let fg = get(colors, foreground).over(bg);
+            let adjusted = readable(fg, bg, opacity, required);
+            log::info!(
+                "Adjusted {} from {} to {} for {:.1}:1 contrast on {}",
+                foreground, fg, adjusted, faded(adjusted, opacity).over(bg).contrast(bg), background,
+            );
+            set(colors, foreground, adjusted);
+            changed = true;
+        }
+        if !changed {
+            return;
+        }
+    }
+    log::warn!("Contrast adjustments didn't settle after {} passes", MAX_PASSES);
+}
+
+/// The closest color to `fg` that, drawn at `opacity`, reaches `required`
+/// against `bg`, moving towards black or white, whichever contrasts more with `bg`.
+fn readable(fg: Color, bg: Color, opacity: f64, required: f64) -> Color {
+    let shown = |color: Color| faded(color, opacity).over(bg).contrast(bg);
+    let black = Color::rgb(0, 0, 0);
+    let white = Color::rgb(255, 255, 255);
+    let target = if shown(black) >= shown(white) { black } else { white };
+    if shown(target) < required {
+        // Not reachable at all; the best possible is the answer
+        return target;
+    }
+
+    // Binary search for the smallest mix that passes
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..16 {
+        let mid = (low + high) / 2.0;
+        if shown(fg.mix(target, mid)) >= required {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+    fg.mix(target, high)
+}
+
+/// The pair as it ends up on screen: translucent fills are composited over
+/// the backdrop, which itself is taken as opaque since we can't know what
+/// the compositor puts behind it, and the foreground is faded by `opacity`.
+fn composited(colors: &ThemeColors, foreground: &str, background: &str, opacity: f64) -> (Color, Color) {
+    let backdrop = colors.background.with_alpha(1.0);
+    let bg = get(colors, background).over(backdrop);
+    (faded(get(colors, foreground), opacity).over(bg), bg)
+}
+
+fn faded(color: Color, opacity: f64) -> Color {
+    color.with_alpha(color.a * opacity)
+}
+
+fn get(colors: &ThemeColors, role: &str) -> Color {
+    match role {
+        "background" => colors.background,
+        "primary" => colors.primary,
+        "danger" => colors.danger,
+        "text" => colors.text,
+        "surface" => colors.surface,
+        "surface_variant" => colors.surface_variant,
+        "outline" => colors.outline,
+        "on_primary" => colors.on_primary,
+        "on_danger" => colors.on_danger,
+        "focus" => colors.focus,
+        "dialog_background" => colors.dialog_background,
+        _ => unreachable!("contrast pairs only use known roles"),
+    }
+}
+
+fn set(colors: &mut ThemeColors, role: &'static str, value: Color) {
+    colors.fallbacks.insert(role, ColorOrigin::Contrast);
+    match role {
+        "primary" => colors.primary = value,
+        "danger" => colors.danger = value,
+        "text" => colors.text = value,
+        "outline" => colors.outline = value,
+        "on_primary" => colors.on_primary = value,
+        "on_danger" => colors.on_danger = value,
+        "focus" => colors.focus = value,
+        _ => unreachable!("contrast pairs only adjust foreground roles"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    // Accents as strict as text, so fixing them moves primary and danger a lot
+    const STRICT_UI: Requirements = Requirements { text: AA_TEXT, ui: AA_TEXT };
+
+    fn colors(roles: &[(&str, &str)]) -> ThemeColors {
+        ThemeColors::from_roles(roles.iter()
+            .map(|(role, value)| (role.to_string(), Color::parse(value).unwrap()))
+            .collect::<BTreeMap<_, _>>())
+    }
+
+    #[test]
+    fn enforce_settles_every_pair_with_a_muted_primary() {
+        // Lifting primary off the background breaks the white on_primary that
+        // was checked against it earlier in the same pass
+        let mut colors = colors(&[
+            ("background", "#1c1b1f"),
+            ("primary", "#4a4458"),
+            ("secondary", "#625b71"),
+            ("text", "#8a8a99"),
+            ("danger", "#5c1f1f"),
+            ("on_primary", "#ffffff"),
+            ("on_danger", "#ffffff"),
+        ]);
+        assert!(!check(&colors, STRICT_UI).is_empty());
+
+        enforce(&mut colors, STRICT_UI, true);
+        let issues = check(&colors, STRICT_UI);
+        assert!(issues.is_empty(), "still failing: {:?}", issues);
+        assert_eq!(colors.origin("primary"), ColorOrigin::Contrast);
+        assert_eq!(colors.origin("on_primary"), ColorOrigin::Contrast);
+    }
+
+    #[test]
+    fn enforce_leaves_accents_alone_without_fix_ui() {
+        let mut colors = colors(&[("background", "#1c1b1f"), ("primary", "#4a4458"), ("text", "#8a8a99")]);
+        enforce(&mut colors, STRICT_UI, false);
+        assert_eq!(colors.primary, Color::parse("#4a4458").unwrap());
+        assert!(check(&colors, Requirements { text: AA_TEXT, ui: f64::MIN }).is_empty());
+    }
+
+    #[test]
+    fn faded_button_labels_are_checked() {
+        // Passes AA at full opacity, but not at the labels' 0.765
+        let colors = colors(&[("background", "#000000"), ("text", "#767676"), ("surface", "#000000")]);
+        let issues = check(&colors, Requirements { text: AA_TEXT, ui: f64::MIN });
+        assert!(!issues.iter().any(|issue| issue.background == "background"));
+        assert!(issues.iter().any(|issue| issue.foreground == "text" && issue.background == "surface"));
+    }
+
+    #[test]
+    fn hovered_buttons_are_checked() {
+        let colors = colors(&[("background", "#ffffff"), ("text", "#000000"), ("surface_variant", "#101010")]);
+        let issues = check(&colors, Requirements { text: AA_TEXT, ui: f64::MIN });
+        assert!(issues.iter().any(|issue| issue.background == "surface_variant"));
+    }
+}
@@ -0,0 +1,491 @@
+// Material You palette generation: HCT color space, tonal palettes and schemes
+//
+// The color science follows Google's material-color-utilities: CAM16 under
+// the default viewing conditions for hue and chroma, CIE L* for tone.
+
+use super::color::Color;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+const SRGB_TO_XYZ: [[f64; 3]; 3] = [
+    [0.41233895, 0.35762064, 0.18051042],
+    [0.2126, 0.7152, 0.0722],
+    [0.01932141, 0.11916382, 0.95034478],
+];
+
+const XYZ_TO_SRGB: [[f64; 3]; 3] = [
+    [3.2413774792388685, -1.5376652402851851, -0.49885366846268053],
+    [-0.9691452513005321, 1.8758853451067872, 0.04156585616912061],
+    [0.05562093689691305, -0.20395524564742123, 1.0571799111220335],
+];
+
+const XYZ_TO_CAM16RGB: [[f64; 3]; 3] = [
+    [0.401288, 0.650173, -0.051461],
+    [-0.250268, 1.204414, 0.045854],
+    [-0.002079, 0.048952, 0.953127],
+];
+
+const CAM16RGB_TO_XYZ: [[f64; 3]; 3] = [
+    [1.8620678, -1.0112547, 0.14918678],
+    [0.38752654, 0.62144744, -0.00897398],
+    [-0.0158415, -0.03412294, 1.0499644],
+];
+
+/// D65 white point
+pub(super) const WHITE_POINT: [f64; 3] = [95.047, 100.0, 108.883];
+
+fn mat_mul(matrix: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    [
+        matrix[0][0] * v[0] + matrix[0][1] * v[1] + matrix[0][2] * v[2],
+        matrix[1][0] * v[0] + matrix[1][1] * v[1] + matrix[1][2] * v[2],
+        matrix[2][0] * v[0] + matrix[2][1] * v[1] + matrix[2][2] * v[2],
+    ]
+}
+
+/// sRGB channel (0..=255) to linear RGB (0..=100)
+fn linearized(channel: u8) -> f64 {
+    let normalized = channel as f64 / 255.0;
+    if normalized <= 0.040449936 {
+        normalized / 12.92 * 100.0
+    } else {
+        ((normalized + 0.055) / 1.055).powf(2.4) * 100.0
+    }
+}
+
+/// Linear RGB (0..=100) to an sRGB channel
+fn delinearized(component: f64) -> u8 {
+    let normalized = component / 100.0;
+    let delinearized = if normalized <= 0.0031308 {
+        normalized * 12.92
+    } else {
+        1.055 * normalized.powf(1.0 / 2.4) - 0.055
+    };
+    (delinearized * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+pub(super) fn lab_f(t: f64) -> f64 {
+    let e = 216.0 / 24389.0;
+    let kappa = 24389.0 / 27.0;
+    if t > e {
+        t.cbrt()
+    } else {
+        (kappa * t + 16.0) / 116.0
+    }
+}
+
+pub(super) fn lab_inv_f(ft: f64) -> f64 {
+    let e = 216.0 / 24389.0;
+    let kappa = 24389.0 / 27.0;
+    let ft3 = ft * ft * ft;
+    if ft3 > e {
+        ft3
+    } else {
+        (116.0 * ft - 16.0) / kappa
+    }
+}
+
+/// CIE L* for a relative luminance Y (0..=100)
+pub fn lstar_from_y(y: f64) -> f64 {
+    116.0 * lab_f(y / 100.0) - 16.0
+}
+
+/// Relative luminance Y (0..=100) for a CIE L*
+pub fn y_from_lstar(lstar: f64) -> f64 {
+    100.0 * lab_inv_f((lstar + 16.0) / 116.0)
+}
+
+pub(super) fn xyz_from_color(color: Color) -> [f64; 3] {
+    mat_mul(&SRGB_TO_XYZ, [linearized(color.r), linearized(color.g), linearized(color.b)])
+}
+
+pub(super) fn color_from_xyz(xyz: [f64; 3]) -> Color {
+    let [r, g, b] = mat_mul(&XYZ_TO_SRGB, xyz);
+    Color::rgb(delinearized(r), delinearized(g), delinearized(b))
+}
+
+fn sanitize_degrees(degrees: f64) -> f64 {
+    degrees.rem_euclid(360.0)
+}
+
+/// CAM16 viewing conditions for a gray-world background at L* 50
+struct ViewingConditions {
+    n: f64,
+    aw: f64,
+    nbb: f64,
+    ncb: f64,
+    c: f64,
+    nc: f64,
+    rgb_d: [f64; 3],
+    fl: f64,
+    z: f64,
+}
+
+impl ViewingConditions {
+    fn standard() -> Self {
+        let adapting_luminance = (200.0 / PI) * y_from_lstar(50.0) / 100.0;
+        let background_lstar: f64 = 50.0;
+        let surround = 2.0;
+
+        let [r_w, g_w, b_w] = mat_mul(&XYZ_TO_CAM16RGB, WHITE_POINT);
+        let f = 0.8 + surround / 10.0;
+        let c = if f >= 0.9 {
+            0.59 + (0.69 - 0.59) * ((f - 0.9) * 10.0)
+        } else {
+            0.525 + (0.59 - 0.525) * ((f - 0.8) * 10.0)
+        };
+        let d = (f * (1.0 - (1.0 / 3.6) * ((-adapting_luminance - 42.0) / 92.0).exp())).clamp(0.0, 1.0);
+        let nc = f;
+        let rgb_d = [
+            d * (100.0 / r_w) + 1.0 - d,
+            d * (100.0 / g_w) + 1.0 - d,
+            d * (100.0 / b_w) + 1.0 - d,
+        ];
+        let k = 1.0 / (5.0 * adapting_luminance + 1.0);
+        let k4 = k * k * k * k;
+        let k4f = 1.0 - k4;
+        let fl = k4 * adapting_luminance + 0.1 * k4f * k4f * (5.0 * adapting_luminance).cbrt();
+        let n = y_from_lstar(background_lstar.max(0.1)) / WHITE_POINT[1];
+        let z = 1.48 + n.sqrt();
+        let nbb = 0.725 / n.powf(0.2);
+        let ncb = nbb;
+
+        let adapted = |component: f64, d: f64| {
+            let factor = (fl * d * component / 100.0).powf(0.42);
+            400.0 * factor / (factor + 27.13)
+        };
+        let rgb_a = [adapted(r_w, rgb_d[0]), adapted(g_w, rgb_d[1]), adapted(b_w, rgb_d[2])];
+        let aw = (2.0 * rgb_a[0] + rgb_a[1] + 0.05 * rgb_a[2]) * nbb;
+
+        Self { n, aw, nbb, ncb, c, nc, rgb_d, fl, z }
+    }
+}
+
+/// Hue (degrees), chroma and CAM16 lightness J of an XYZ color
+fn cam16_from_xyz(vc: &ViewingConditions, xyz: [f64; 3]) -> (f64, f64, f64) {
+    let rgb_c = mat_mul(&XYZ_TO_CAM16RGB, xyz);
+    let adapt = |component: f64, d: f64| {
+        let rd = d * component;
+        let af = (vc.fl * rd.abs() / 100.0).powf(0.42);
+        rd.signum() * 400.0 * af / (af + 27.13)
+    };
+    let r_a = adapt(rgb_c[0], vc.rgb_d[0]);
+    let g_a = adapt(rgb_c[1], vc.rgb_d[1]);
+    let b_a = adapt(rgb_c[2], vc.rgb_d[2]);
+
+    let a = (11.0 * r_a + -12.0 * g_a + b_a) / 11.0;
+    let b = (r_a + g_a - 2.0 * b_a) / 9.0;
+    let u = (20.0 * r_a + 20.0 * g_a + 21.0 * b_a) / 20.0;
+    let p2 = (40.0 * r_a + 20.0 * g_a + b_a) / 20.0;
+
+    let hue = sanitize_degrees(b.atan2(a).to_degrees());
+    let ac = p2 * vc.nbb;
+    let j = 100.0 * (ac / vc.aw).powf(vc.c * vc.z);
+
+    let hue_prime = if hue < 20.14 { hue + 360.0 } else { hue };
+    let e_hue = 0.25 * ((hue_prime.to_radians() + 2.0).cos() + 3.8);
+    let p1 = 50000.0 / 13.0 * e_hue * vc.nc * vc.ncb;
+    let t = p1 * a.hypot(b) / (u + 0.305);
+    let alpha = t.powf(0.9) * (1.64 - 0.29f64.powf(vc.n)).powf(0.73);
+    let chroma = alpha * (j / 100.0).sqrt();
+
+    (hue, chroma, j)
+}
+
+/// Inverse CAM16: XYZ for a lightness J, chroma and hue (degrees)
+fn xyz_from_cam16(vc: &ViewingConditions, j: f64, chroma: f64, hue: f64) -> [f64; 3] {
+    let alpha = if chroma == 0.0 || j == 0.0 { 0.0 } else { chroma / (j / 100.0).sqrt() };
+    let t = (alpha / (1.64 - 0.29f64.powf(vc.n)).powf(0.73)).powf(1.0 / 0.9);
+    let h_rad = hue.to_radians();
+
+    let e_hue = 0.25 * ((h_rad + 2.0).cos() + 3.8);
+    let ac = vc.aw * (j / 100.0).powf(1.0 / vc.c / vc.z);
+    let p1 = e_hue * (50000.0 / 13.0) * vc.nc * vc.ncb;
+    let p2 = ac / vc.nbb;
+
+    let (h_sin, h_cos) = h_rad.sin_cos();
+    let gamma = 23.0 * (p2 + 0.305) * t / (23.0 * p1 + 11.0 * t * h_cos + 108.0 * t * h_sin);
+    let a = gamma * h_cos;
+    let b = gamma * h_sin;
+
+    let r_a = (460.0 * p2 + 451.0 * a + 288.0 * b) / 1403.0;
+    let g_a = (460.0 * p2 - 891.0 * a - 261.0 * b) / 1403.0;
+    let b_a = (460.0 * p2 - 220.0 * a - 6300.0 * b) / 1403.0;
+
+    let unadapt = |component: f64, d: f64| {
+        let base = (27.13 * component.abs() / (400.0 - component.abs())).max(0.0);
+        component.signum() * (100.0 / vc.fl) * base.powf(1.0 / 0.42) / d
+    };
+    let rgb_f = [unadapt(r_a, vc.rgb_d[0]), unadapt(g_a, vc.rgb_d[1]), unadapt(b_a, vc.rgb_d[2])];
+
+    mat_mul(&CAM16RGB_TO_XYZ, rgb_f)
+}
+
+/// A color in Material's HCT space: CAM16 hue and chroma with L* tone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hct {
+    pub hue: f64,
+    pub chroma: f64,
+    pub tone: f64,
+}
+
+impl Hct {
+    pub fn from_color(color: Color) -> Self {
+        let xyz = xyz_from_color(color);
+        let (hue, chroma, _) = cam16_from_xyz(&ViewingConditions::standard(), xyz);
+        Self { hue, chroma, tone: lstar_from_y(xyz[1]) }
+    }
+
+    pub fn new(hue: f64, chroma: f64, tone: f64) -> Self {
+        Self { hue: sanitize_degrees(hue), chroma: chroma.max(0.0), tone: tone.clamp(0.0, 100.0) }
+    }
+
+    /// The closest sRGB color: exact hue and tone, with chroma reduced until it fits the gamut
+    pub fn to_color(self) -> Color {
+        if self.chroma < 0.5 || self.tone <= 0.0 || self.tone >= 100.0 {
+            let gray = delinearized(y_from_lstar(self.tone));
+            return Color::rgb(gray, gray, gray);
+        }
+
+        let vc = ViewingConditions::standard();
+        if let Some(linear) = Self::solve(&vc, self.hue, self.chroma, self.tone) {
+            return Self::linear_to_color(linear);
+        }
+
+        // Binary search for the highest chroma that is still in gamut
+        let (mut low, mut high) = (0.0, self.chroma);
+        let mut best = None;
+        while high - low > 0.4 {
+            let mid = (low + high) / 2.0;
+            match Self::solve(&vc, self.hue, mid, self.tone) {
+                Some(linear) => {
+                    best = Some(linear);
+                    low = mid;
+                }
+                None => high = mid,
+            }
+        }
+
+        match best {
+            Some(linear) => Self::linear_to_color(linear),
+            None => Hct::new(self.hue, 0.0, self.tone).to_color(),
+        }
+    }
+
+    /// Find the CAM16 lightness that yields `tone` at this hue and chroma,
+    /// returning the linear RGB if the result is inside the sRGB gamut.
+    fn solve(vc: &ViewingConditions, hue: f64, chroma: f64, tone: f64) -> Option<[f64; 3]> {
+        let target_y = y_from_lstar(tone);
+        let (mut low, mut high) = (0.0, 100.0);
+        let mut xyz = [0.0; 3];
+
+        for _ in 0..40 {
+            let j = (low + high) / 2.0;
+            xyz = xyz_from_cam16(vc, j, chroma, hue);
+            if xyz[1] < target_y {
+                low = j;
+            } else {
+                high = j;
+            }
+        }
+
+        if (lstar_from_y(xyz[1]) - tone).abs() > 0.2 {
+            return None;
+        }
+
+        let linear = mat_mul(&XYZ_TO_SRGB, xyz);
+        linear.iter()
+            .all(|component| (-0.01..=100.01).contains(component))
+            .then_some(linear)
+    }
+
+    fn linear_to_color(linear: [f64; 3]) -> Color {
+        Color::rgb(delinearized(linear[0]), delinearized(linear[1]), delinearized(linear[2]))
+    }
+}
+
+/// All tones of a single hue and chroma
+#[derive(Debug, Clone, Copy)]
+pub struct TonalPalette {
+    pub hue: f64,
+    pub chroma: f64,
+}
+
+impl TonalPalette {
+    pub fn new(hue: f64, chroma: f64) -> Self {
+        Self { hue, chroma }
+    }
+
+    pub fn tone(&self, tone: f64) -> Color {
+        Hct::new(self.hue, self.chroma, tone).to_color()
+    }
+}
+
+/// Key palettes of Material's default "tonal spot" scheme
+struct CorePalettes {
+    primary: TonalPalette,
+    secondary: TonalPalette,
+    tertiary: TonalPalette,
+    neutral: TonalPalette,
+    neutral_variant: TonalPalette,
+    error: TonalPalette,
+}
+
+impl CorePalettes {
+    fn tonal_spot(seed: Color) -> Self {
+        let hue = Hct::from_color(seed).hue;
+        Self {
+            primary: TonalPalette::new(hue, 36.0),
+            secondary: TonalPalette::new(hue, 16.0),
+            tertiary: TonalPalette::new(sanitize_degrees(hue + 60.0), 24.0),
+            neutral: TonalPalette::new(hue, 6.0),
+            neutral_variant: TonalPalette::new(hue, 8.0),
+            error: TonalPalette::new(25.0, 84.0),
+        }
+    }
+}
+
+/// Generate every Material 3 color role for a seed color, keyed by matugen's role names.
+pub fn scheme_from_seed(seed: Color, dark: bool) -> BTreeMap<String, Color> {
+    let palettes = CorePalettes::tonal_spot(seed);
+    let mut roles = BTreeMap::new();
+    let mut set = |role: &str, palette: &TonalPalette, tone: f64| {
+        roles.insert(role.to_string(), palette.tone(tone));
+    };
+
+    // (role, light tone, dark tone) for each accent palette
+    let accents = [
+        ("primary", &palettes.primary),
+        ("secondary", &palettes.secondary),
+        ("tertiary", &palettes.tertiary),
+        ("error", &palettes.error),
+    ];
+    for (name, palette) in accents {
+        let (base, on_base, container, on_container) = if dark {
+            (80.0, 20.0, 30.0, 90.0)
+        } else {
+            (40.0, 100.0, 90.0, 10.0)
+        };
+        set(name, palette, base);
+        set(&format!("on_{}", name), palette, on_base);
+        set(&format!("{}_container", name), palette, container);
+        set(&format!("on_{}_container", name), palette, on_container);
+
+        // Fixed roles are identical in light and dark schemes
+        if name != "error" {
+            set(&format!("{}_fixed", name), palette, 90.0);
+            set(&format!("{}_fixed_dim", name), palette, 80.0);
+            set(&format!("on_{}_fixed", name), palette, 10.0);
+            set(&format!("on_{}_fixed_variant", name), palette, 30.0);
+        }
+    }
+
+    let neutral = &palettes.neutral;
+    let neutral_variant = &palettes.neutral_variant;
+    let tones: &[(&str, &TonalPalette, f64, f64)] = &[
+        ("background", neutral, 98.0, 6.0),
+        ("on_background", neutral, 10.0, 90.0),
+        ("surface", neutral, 98.0, 6.0),
+        ("surface_dim", neutral, 87.0, 6.0),
+        ("surface_bright", neutral, 98.0, 24.0),
+        ("surface_container_lowest", neutral, 100.0, 4.0),
+        ("surface_container_low", neutral, 96.0, 10.0),
+        ("surface_container", neutral, 94.0, 12.0),
+        ("surface_container_high", neutral, 92.0, 17.0),
+        ("surface_container_highest", neutral, 90.0, 22.0),
+        ("on_surface", neutral, 10.0, 90.0),
+        ("surface_variant", neutral_variant, 90.0, 30.0),
+        ("on_surface_variant", neutral_variant, 30.0, 80.0),
+        ("inverse_surface", neutral, 20.0, 90.0),
+        ("inverse_on_surface", neutral, 95.0, 20.0),
+        ("outline", neutral_variant, 50.0, 60.0),
+        ("outline_variant", neutral_variant, 80.0, 30.0),
+        ("shadow", neutral, 0.0, 0.0),
+        ("scrim", neutral, 0.0, 0.0),
+        ("inverse_primary", &palettes.primary, 80.0, 40.0),
+        ("surface_tint", &palettes.primary, 40.0, 80.0),
+    ];
+    for (role, palette, light, dark_tone) in tones {
+        set(role, palette, if dark { *dark_tone } else { *light });
+    }
+
+    roles.insert("source_color".to_string(), seed);
+    roles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The Material baseline seed
+    const BASELINE: &str = "#6750a4";
+
+    fn scheme(seed: &str, dark: bool) -> BTreeMap<String, String> {
+        scheme_from_seed(Color::parse(seed).unwrap(), dark).into_iter()
+            .map(|(role, color)| (role, color.to_string()))
+            .collect()
+    }
+
+    fn assert_roles(scheme: &BTreeMap<String, String>, expected: &[(&str, &str)]) {
+        for (role, color) in expected {
+            assert_eq!(scheme[*role], *color, "{}", role);
+        }
+    }
+
+    // Expected values are those of Material's own tonal spot scheme
+
+    #[test]
+    fn baseline_light_scheme() {
+        assert_roles(&scheme(BASELINE, false), &[
+            ("primary", "#65558f"),
+            ("on_primary", "#ffffff"),
+            ("primary_container", "#e9ddff"),
+            ("secondary", "#625b71"),
+            ("tertiary", "#7e5260"),
+            ("error", "#ba1a1a"),
+            ("background", "#fdf7ff"),
+            ("on_surface", "#1d1b20"),
+            ("surface_container", "#f2ecf4"),
+            ("surface_variant", "#e7e0eb"),
+        ]);
+    }
+
+    #[test]
+    fn baseline_dark_scheme() {
+        assert_roles(&scheme(BASELINE, true), &[
+            ("primary", "#cfbdfe"),
+            ("primary_container", "#4d3d75"),
+            ("secondary", "#cbc2db"),
+            ("tertiary", "#efb8c8"),
+            ("error", "#ffb4ab"),
+            ("background", "#141218"),
+            ("on_surface", "#e6e0e9"),
+            ("surface_container", "#211f24"),
+            ("surface_variant", "#49454e"),
+        ]);
+    }
+
+    #[test]
+    fn fixed_roles_are_the_same_in_both_schemes() {
+        let (light, dark) = (scheme(BASELINE, false), scheme(BASELINE, true));
+        for role in ["primary_fixed", "primary_fixed_dim", "on_secondary_fixed", "tertiary_fixed"] {
+            assert_eq!(light[role], dark[role], "{}", role);
+        }
+        assert_eq!(light["source_color"], BASELINE);
+    }
+
+    #[test]
+    fn grey_seed_still_gets_accent_colors() {
+        // The seed has no chroma, but tonal spot gives primary its own
+        for dark in [false, true] {
+            let roles = scheme_from_seed(Color::parse("#808080").unwrap(), dark);
+            let tone = |role: &str| Hct::from_color(roles[role]).tone;
+            let chroma = |role: &str| Hct::from_color(roles[role]).chroma;
+
+            let (primary, background) = if dark { (80.0, 6.0) } else { (40.0, 98.0) };
+            assert!((tone("primary") - primary).abs() < 1.0, "primary tone {}", tone("primary"));
+            assert!((tone("background") - background).abs() < 1.0, "background tone {}", tone("background"));
+            assert!(chroma("primary") > 30.0, "primary chroma {}", chroma("primary"));
+            assert!(chroma("background") < 8.0, "background chroma {}", chroma("background"));
+        }
+    }
+}
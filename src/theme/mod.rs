@@ -1,70 +1,853 @@
 // Theme module
 
-use crate::config::{ThemeConfig, ManualColors};
+mod cache;
+mod color;
+mod command;
+mod contrast;
+mod material;
+mod presets;
+mod quantize;
+mod system;
+mod template;
+mod terminal;
+mod wallpaper;
+
+pub use color::Color;
+pub use contrast::ContrastIssue;
+
+use crate::config::{EffectsConfig, ThemeConfig, ManualColors, Scheme, SourceKind};
 use anyhow::{Result, anyhow};
 
-use std::process::Command;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use notify::{Watcher, RecursiveMode, RecommendedWatcher};
-use std::sync::mpsc::channel;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, OnceLock};
+
+/// How long a watched file has to stay quiet before it is re-read
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Last successful output of the theme command
+const COMMAND_CACHE_FILE: &str = "command-output.json";
+/// Resolved colors and generated CSS from the last launch
+const THEME_CACHE_FILE: &str = "theme.json";
 
+const BASE_TEMPLATE: &str = include_str!("templates/base.css");
+const HOVER_TEMPLATE: &str = include_str!("templates/hover.css");
+const ANIMATION_TEMPLATE: &str = include_str!("templates/animations.css");
 
+/// Number of buttons the entrance animation staggers
+const STAGGERED_BUTTONS: u32 = 5;
+
+/// Changes reported by [`ThemeManager::start_watcher`]
 #[derive(Debug, Clone)]
+pub enum ThemeUpdate {
+    /// The theme file changed and resolved to new colors
+    Colors(Box<ThemeColors>),
+    /// The CSS template changed; regenerate the stylesheet
+    Template,
+    /// The user stylesheet changed; reload it
+    Stylesheet,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ThemeColors {
-    pub background: String,
-    pub primary: String,
-    pub secondary: String,
-    pub text: String,
-    pub danger: String,
+    pub background: Color,
+    pub primary: Color,
+    pub secondary: Color,
+    pub text: Color,
+    pub danger: Color,
+    /// Button fill
+    pub surface: Color,
+    /// Fill of hovered buttons
+    pub surface_variant: Color,
+    /// Borders and separators
+    pub outline: Color,
+    /// Text drawn on a `primary` fill
+    pub on_primary: Color,
+    /// Text drawn on a `danger` fill
+    pub on_danger: Color,
+    /// Keyboard focus ring
+    pub focus: Color,
+    pub dialog_background: Color,
+    /// Base color of drop and text shadows; templates set the opacity
+    pub shadow: Color,
+    /// Material 3 roles (surface_container, on_primary, ...) provided by the theme source
+    pub roles: BTreeMap<String, Color>,
+    /// Semantic roles the source didn't provide; the rest came from the source
+    fallbacks: BTreeMap<&'static str, ColorOrigin>,
+    /// Where the source read each semantic role it provided, e.g. `colors.color4`
+    keys: BTreeMap<String, String>,
+    /// The same for `roles`
+    material_keys: BTreeMap<String, String>,
+}
+
+/// Where a resolved color came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorOrigin {
+    /// Provided by the theme source, read from the given key if the source has keys
+    Source(Option<String>),
+    /// Derived from the base colors; the source had none of the given keys
+    Derived(Vec<String>),
+    /// Missing base color, taken from the built-in palette; the source had
+    /// none of the given keys
+    Default(Vec<String>),
+    /// Changed to meet the contrast settings
+    Contrast,
+}
+
+impl Default for ThemeColors {
+    fn default() -> Self {
+        Self::from_roles(BTreeMap::new())
+    }
+}
+
+impl ThemeColors {
+    /// Build colors from the roles a source provided.
+    ///
+    /// Missing base roles fall back to the default palette and missing
+    /// semantic roles are derived from the base five. Material roles that
+    /// aren't semantic roles are kept in `roles`.
+    pub fn from_roles(mut explicit: BTreeMap<String, Color>) -> Self {
+        let defaults = ManualColors::default();
+        let mut fallbacks = BTreeMap::new();
+        let mut take = |role: &'static str, default: &str| {
+            explicit.remove(role).unwrap_or_else(|| {
+                fallbacks.insert(role, ColorOrigin::Default(Vec::new()));
+                Color::parse(default).expect("default colors are valid")
+            })
+        };
+        let background = take("background", &defaults.background);
+        let primary = take("primary", &defaults.primary);
+        let secondary = take("secondary", &defaults.secondary);
+        let text = take("text", &defaults.text);
+        let danger = take("danger", &defaults.danger);
+
+        // Whichever of the palette's own foreground and background reads better
+        let readable_on = |fill: Color| {
+            let dark = background.with_alpha(1.0);
+            if text.contrast(fill) >= dark.contrast(fill) { text } else { dark }
+        };
+        let black = Color::rgb(0, 0, 0);
+        let mut derive = |role: &'static str, derived: Color| {
+            explicit.remove(role).unwrap_or_else(|| {
+                fallbacks.insert(role, ColorOrigin::Derived(Vec::new()));
+                derived
+            })
+        };
+
+        let surface = derive("surface", text.with_alpha(0.08));
+        let surface_variant = derive("surface_variant", text.with_alpha(0.15));
+        let outline = derive("outline", text.with_alpha(0.3));
+        let on_primary = derive("on_primary", readable_on(primary));
+        let on_danger = derive("on_danger", readable_on(danger));
+        let focus = derive("focus", primary);
+        let dialog_background = derive("dialog_background", background.mix(black, 0.6).with_alpha(0.9));
+        let shadow = derive("shadow", background.mix(black, 0.8).with_alpha(1.0));
+
+        explicit.retain(|role, _| MATERIAL_ROLES.contains(&role.as_str()));
+
+        Self {
+            background,
+            primary,
+            secondary,
+            text,
+            danger,
+            surface,
+            surface_variant,
+            outline,
+            on_primary,
+            on_danger,
+            focus,
+            dialog_background,
+            shadow,
+            roles: explicit,
+            fallbacks,
+            keys: BTreeMap::new(),
+            material_keys: BTreeMap::new(),
+        }
+    }
+
+    /// Record where the source read each semantic role (`keys`, by role),
+    /// and which keys it looked for the ones it didn't have
+    fn with_keys(mut self, keys: BTreeMap<String, String>, looked_for: impl Fn(&str) -> Vec<String>) -> Self {
+        for (role, origin) in &mut self.fallbacks {
+            if let ColorOrigin::Derived(missing) | ColorOrigin::Default(missing) = origin {
+                *missing = looked_for(role);
+            }
+        }
+        self.keys = keys;
+        self
+    }
+
+    /// Where the color for `role` came from
+    pub fn origin(&self, role: &str) -> ColorOrigin {
+        match self.fallbacks.get(role) {
+            Some(origin) => origin.clone(),
+            None => ColorOrigin::Source(self.keys.get(role).cloned()),
+        }
+    }
+
+    /// Where the Material role `role` in `roles` came from
+    pub fn material_origin(&self, role: &str) -> ColorOrigin {
+        ColorOrigin::Source(self.material_keys.get(role).cloned())
+    }
+
+    /// The colors and their origins as stored in the theme cache
+    fn to_cached(&self) -> CachedColors {
+        CachedColors {
+            semantic: self.semantic_colors().into_iter()
+                .map(|(role, value)| (role.to_string(), value.to_string()))
+                .collect(),
+            roles: self.roles.iter().map(|(role, value)| (role.clone(), value.to_string())).collect(),
+            fallbacks: self.fallbacks.iter().map(|(role, origin)| (role.to_string(), origin.clone())).collect(),
+            keys: self.keys.clone(),
+            material_keys: self.material_keys.clone(),
+        }
+    }
+
+    /// The reverse of `to_cached`, or `None` if the entry is incomplete
+    fn from_cached(cached: CachedColors) -> Option<Self> {
+        let parse = |colors: BTreeMap<String, String>| -> Option<BTreeMap<String, Color>> {
+            colors.into_iter().map(|(role, value)| Some((role, Color::parse(&value).ok()?))).collect()
+        };
+        let semantic = parse(cached.semantic)?;
+        if !SEMANTIC_ROLES.iter().all(|role| semantic.contains_key(*role)) {
+            return None;
+        }
+        let fallbacks = cached.fallbacks.into_iter()
+            .map(|(role, origin)| Some((*SEMANTIC_ROLES.iter().find(|known| **known == role)?, origin)))
+            .collect::<Option<_>>()?;
+        Some(Self {
+            roles: parse(cached.roles)?,
+            fallbacks,
+            keys: cached.keys,
+            material_keys: cached.material_keys,
+            ..Self::from_roles(semantic)
+        })
+    }
+
+    /// The semantic roles in display order
+    pub fn semantic_colors(&self) -> [(&'static str, Color); 13] {
+        [
+            ("background", self.background),
+            ("primary", self.primary),
+            ("secondary", self.secondary),
+            ("text", self.text),
+            ("danger", self.danger),
+            ("surface", self.surface),
+            ("surface_variant", self.surface_variant),
+            ("outline", self.outline),
+            ("on_primary", self.on_primary),
+            ("on_danger", self.on_danger),
+            ("focus", self.focus),
+            ("dialog_background", self.dialog_background),
+            ("shadow", self.shadow),
+        ]
+    }
+
+    /// Every named color: the Material roles overlaid with the semantic roles
+    pub fn named_colors(&self) -> BTreeMap<String, Color> {
+        let mut colors = self.roles.clone();
+        for (role, value) in self.semantic_colors() {
+            colors.insert(role.to_string(), value);
+        }
+        colors
+    }
+}
+
+/// The roles `ThemeColors` has fields for
+const SEMANTIC_ROLES: &[&str] = &[
+    "background",
+    "primary",
+    "secondary",
+    "text",
+    "danger",
+    "surface",
+    "surface_variant",
+    "outline",
+    "on_primary",
+    "on_danger",
+    "focus",
+    "dialog_background",
+    "shadow",
+];
+
+fn is_known_role(role: &str) -> bool {
+    SEMANTIC_ROLES.contains(&role) || MATERIAL_ROLES.contains(&role)
+}
+
+/// Parse a color read from a theme source, naming the source and key on failure
+fn parse_color(value: &str, source: &str, key: &str) -> Result<Color> {
+    Color::parse(value)
+        .map_err(|e| anyhow!("Invalid color for '{}' in {} theme source: {}", key, source, e))
+}
+
+/// Material 3 color roles understood in matugen output
+const MATERIAL_ROLES: &[&str] = &[
+    "source_color",
+    "background",
+    "on_background",
+    "surface",
+    "surface_dim",
+    "surface_bright",
+    "surface_container_lowest",
+    "surface_container_low",
+    "surface_container",
+    "surface_container_high",
+    "surface_container_highest",
+    "on_surface",
+    "surface_variant",
+    "on_surface_variant",
+    "surface_tint",
+    "inverse_surface",
+    "inverse_on_surface",
+    "outline",
+    "outline_variant",
+    "shadow",
+    "scrim",
+    "primary",
+    "on_primary",
+    "primary_container",
+    "on_primary_container",
+    "inverse_primary",
+    "primary_fixed",
+    "primary_fixed_dim",
+    "on_primary_fixed",
+    "on_primary_fixed_variant",
+    "secondary",
+    "on_secondary",
+    "secondary_container",
+    "on_secondary_container",
+    "secondary_fixed",
+    "secondary_fixed_dim",
+    "on_secondary_fixed",
+    "on_secondary_fixed_variant",
+    "tertiary",
+    "on_tertiary",
+    "tertiary_container",
+    "on_tertiary_container",
+    "tertiary_fixed",
+    "tertiary_fixed_dim",
+    "on_tertiary_fixed",
+    "on_tertiary_fixed_variant",
+    "error",
+    "on_error",
+    "error_container",
+    "on_error_container",
+];
+
+/// Color scheme variants matugen may emit, in lookup order for each selected scheme
+fn scheme_candidates(scheme: &str) -> &'static [&'static str] {
+    match scheme {
+        "light" => &["light", "default"],
+        "amoled" => &["amoled", "dark", "default"],
+        _ => &["dark", "default"],
+    }
+}
+
+/// Material 3 roles each semantic role is taken from, in order of preference
+const MATERIAL_SEMANTICS: &[(&str, &[&str])] = &[
+    ("background", &["surface", "background"]),
+    ("primary", &["primary"]),
+    ("secondary", &["secondary", "tertiary"]),
+    ("text", &["on_surface", "on_background"]),
+    ("danger", &["error"]),
+    ("surface", &["surface_container"]),
+    ("surface_variant", &["surface_container_highest", "surface_variant"]),
+    ("outline", &["outline"]),
+    ("on_primary", &["on_primary"]),
+    ("on_danger", &["on_error"]),
+    ("dialog_background", &["surface_container_high"]),
+    ("shadow", &["shadow"]),
+];
+
+/// Default terminal palette slot for each theme role, shared by pywal and the
+/// terminal config sources
+const TERMINAL_DEFAULT_MAPPING: &[(&str, &str)] = &[
+    ("background", "background"),
+    ("primary", "color4"),
+    ("secondary", "color6"),
+    ("text", "foreground"),
+    ("danger", "color1"),
+];
+
+/// Names of the built-in theme presets
+pub fn preset_names() -> impl Iterator<Item = &'static str> {
+    presets::names()
+}
+
+/// Every valid theme source name
+pub fn source_names() -> impl Iterator<Item = &'static str> {
+    SourceKind::ALL.iter().map(|kind| kind.as_str())
+}
+
+/// Whether `source` reads its colors from `file_path`
+pub fn reads_file(source: SourceKind) -> bool {
+    matches!(source, SourceKind::File | SourceKind::Base16) || is_terminal(source)
+}
+
+/// Whether `source` has dark and light variants to pick from. Manual colors,
+/// Base16 schemes and terminal configs are a single palette.
+fn has_scheme_variants(source: SourceKind) -> bool {
+    matches!(
+        source,
+        SourceKind::System | SourceKind::File | SourceKind::Command | SourceKind::Seed
+            | SourceKind::Wallpaper | SourceKind::Preset
+    )
+}
+
+/// Whether `source` is one of the terminal config formats
+fn is_terminal(source: SourceKind) -> bool {
+    terminal::FORMATS.contains(&source.as_str())
+}
+
+/// Files the watcher thread keeps an eye on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WatchTarget {
+    Theme,
+    Template,
+    Stylesheet,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedCommand {
+    command: String,
+    output: String,
+}
+
+/// Everything the resolved theme depends on; a cached theme is only used
+/// when all of it is unchanged. The desktop's dark/light preference isn't part
+/// of it, since asking for it is a D-Bus round trip; see `check_cached_scheme`.
+#[derive(Serialize, Deserialize, PartialEq)]
+struct ThemeCacheKey {
+    version: String,
+    theme: serde_json::Value,
+    effects: serde_json::Value,
+    files: Vec<(PathBuf, Option<u128>)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedTheme {
+    key: ThemeCacheKey,
+    /// The variant the colors were resolved for
+    scheme: String,
+    colors: CachedColors,
+    css: String,
+    command_pending: bool,
 }
 
+/// `ThemeColors` in the cache. Semantic and Material roles are kept apart,
+/// since some share a name (`background`, `surface`, ...) but not a value.
+#[derive(Serialize, Deserialize)]
+struct CachedColors {
+    semantic: BTreeMap<String, String>,
+    roles: BTreeMap<String, String>,
+    fallbacks: BTreeMap<String, ColorOrigin>,
+    keys: BTreeMap<String, String>,
+    material_keys: BTreeMap<String, String>,
+}
+
+/// Resolved colors and how the fallback chain arrived at them
+pub struct Resolution {
+    pub colors: ThemeColors,
+    /// The source that produced the colors; `None` means the built-in palette
+    pub source: Option<SourceKind>,
+    /// Sources tried before it, with the reason each was skipped
+    pub skipped: Vec<(SourceKind, String)>,
+    command_pending: bool,
+}
+
+/// Colors and stylesheet to open the menu with
+pub struct StartupTheme {
+    pub colors: ThemeColors,
+    pub css: String,
+    /// The theme command still has to run; see `ThemeManager::refresh_command_colors`
+    pub command_pending: bool,
+    /// The variant a cached theme was resolved for, when it should follow the
+    /// desktop; see `ThemeManager::check_cached_scheme`
+    pub cached_scheme: Option<String>,
+}
+
+/// Default base16 slot for each theme role, following the base16 styling guidelines
+const BASE16_DEFAULT_MAPPING: &[(&str, &str)] = &[
+    ("background", "base00"),
+    ("surface", "base01"),
+    ("dialog_background", "base01"),
+    ("surface_variant", "base02"),
+    ("outline", "base03"),
+    ("text", "base05"),
+    ("danger", "base08"),
+    ("secondary", "base0c"),
+    ("primary", "base0d"),
+    ("on_primary", "base00"),
+    ("on_danger", "base00"),
+];
+
 #[derive(Clone)]
 pub struct ThemeManager {
     config: ThemeConfig,
+    effects: EffectsConfig,
+    use_cache: bool,
+    /// GTK settings read on the main thread, for resolving on another one
+    gtk_settings: Option<system::GtkThemeSettings>,
+    /// The desktop's settings for the resolution in progress, read at most
+    /// once; see `resolving`
+    desktop: OnceLock<system::Desktop>,
 }
 
 impl ThemeManager {
-    pub fn new(config: ThemeConfig) -> Result<Self> {
-        Ok(Self { config })
+    pub fn new(config: ThemeConfig, effects: EffectsConfig) -> Result<Self> {
+        Ok(Self { config, effects, use_cache: true, gtk_settings: None, desktop: OnceLock::new() })
+    }
+
+    /// Ignore the cached theme on startup; it is still rewritten afterwards
+    pub fn bypass_cache(&mut self) {
+        self.use_cache = false;
     }
 
+    /// Resolve colors from the first configured source that works.
+    ///
+    /// Sources are tried in order; if all of them fail the built-in palette is
+    /// used, so a broken theme never keeps the menu from opening.
     pub fn get_colors(&self) -> Result<ThemeColors> {
-        match self.config.source.as_str() {
-            "manual" => self.get_manual_colors(),
-            "system" => self.get_system_colors(),
-            "file" => self.get_file_colors(),
-            "command" => self.get_command_colors(),
-            _ => Err(anyhow!("Unknown theme source: {}", self.config.source)),
+        Ok(self.explain_colors().colors)
+    }
+
+    /// Like `get_colors`, but also report which source was used and why the
+    /// ones before it were skipped
+    pub fn explain_colors(&self) -> Resolution {
+        self.resolving().explain()
+    }
+
+    fn explain(&self) -> Resolution {
+        let mut resolution = self.resolve_colors(false);
+        resolution.colors = self.finish(resolution.colors);
+        resolution
+    }
+
+    /// The theme to open the menu with, as fast as possible.
+    ///
+    /// Reuses the colors and CSS of the last launch when nothing they depend
+    /// on changed. Otherwise colors are resolved like `get_colors`, except
+    /// that the theme command isn't waited on: it answers with its last good
+    /// output, or is skipped if there is none yet, and `command_pending` asks
+    /// for `refresh_command_colors` to run it in the background.
+    pub fn get_startup_theme(&self) -> Result<StartupTheme> {
+        let manager = self.resolving();
+        manager.startup_theme()
+    }
+
+    fn startup_theme(&self) -> Result<StartupTheme> {
+        let key = self.cache_key();
+        if let (true, Some(key)) = (self.use_cache, &key) {
+            if let Some((mut theme, scheme)) = Self::read_cached_theme(key) {
+                log::debug!("Using cached theme");
+                if self.follows_desktop() {
+                    theme.cached_scheme = Some(scheme);
+                }
+                return Ok(theme);
+            }
+        }
+
+        let resolution = self.resolve_colors(true);
+        let colors = self.finish(resolution.colors);
+        let theme = StartupTheme {
+            css: self.generate_css(&colors),
+            colors,
+            command_pending: resolution.command_pending,
+            cached_scheme: None,
+        };
+        if let Some(key) = key {
+            self.write_cached_theme(key, &theme);
+        }
+        Ok(theme)
+    }
+
+    /// Check on a background thread whether the desktop still prefers the
+    /// `scheme` a cached theme was resolved for, and send fresh colors if not.
+    pub fn check_cached_scheme(&self, scheme: String, sender: Sender<ThemeUpdate>) {
+        let manager = self.with_gtk_settings(system::gtk_theme_settings());
+        std::thread::spawn(move || {
+            if manager.resolve_scheme() == scheme {
+                return;
+            }
+            log::info!("The desktop no longer prefers the cached {} theme, reloading colors", scheme);
+            let colors = manager.explain().colors;
+            if let Some(key) = manager.cache_key() {
+                let css = manager.generate_css(&colors);
+                let theme = StartupTheme { colors: colors.clone(), css, command_pending: false, cached_scheme: None };
+                manager.write_cached_theme(key, &theme);
+            }
+            let _ = sender.send(ThemeUpdate::Colors(Box::new(colors)));
+        });
+    }
+
+    fn resolve_colors(&self, defer_command: bool) -> Resolution {
+        let mut resolution = Resolution {
+            colors: ThemeColors::default(),
+            source: None,
+            skipped: Vec::new(),
+            command_pending: false,
+        };
+        for &source in self.config.source.sources() {
+            if defer_command && source == SourceKind::Command {
+                resolution.command_pending = true;
+                match self.cached_command_colors() {
+                    Some(colors) => {
+                        log::debug!("Using cached output of the theme command until it finishes");
+                        resolution.colors = colors;
+                        resolution.source = Some(source);
+                        return resolution;
+                    }
+                    None => {
+                        log::debug!("Theme command has no cached output yet, trying the next source");
+                        resolution.skipped.push((source, "still running".to_string()));
+                        continue;
+                    }
+                }
+            }
+
+            match self.get_source_colors(source) {
+                Ok(colors) => {
+                    log::debug!("Using theme source '{}'", source);
+                    resolution.colors = colors;
+                    resolution.source = Some(source);
+                    return resolution;
+                }
+                Err(e) => {
+                    log::warn!("Skipping theme source '{}': {}", source, e);
+                    resolution.skipped.push((source, e.to_string()));
+                }
+            }
+        }
+
+        if !resolution.command_pending {
+            log::warn!("No theme source could be used, falling back to the built-in palette");
+        }
+        resolution
+    }
+
+    /// The cache key for the current config, or `None` if the result depends
+    /// on something that can't be checked without resolving it
+    fn cache_key(&self) -> Option<ThemeCacheKey> {
+        let sources = self.config.source.sources();
+        // Accent colors and wallpaper commands answer differently without any config change
+        let uncacheable = |source: &SourceKind| match source {
+            SourceKind::System => true,
+            SourceKind::Wallpaper => self.config.wallpaper.is_none(),
+            _ => false,
+        };
+        if sources.iter().any(uncacheable) {
+            return None;
+        }
+
+        let mut paths: Vec<PathBuf> = self.config.file_path.iter()
+            .chain(&self.config.wallpaper)
+            .chain(&self.config.css_template)
+            .cloned()
+            .collect();
+        // Terminal configs can pull in other files, such as kitty's `include`
+        for &source in sources.iter().filter(|&&source| is_terminal(source)) {
+            let format = source.as_str();
+            if let Some(path) = self.config.file_path.clone().or_else(|| terminal::default_path(format)) {
+                paths.extend(terminal::files(format, &path));
+            }
+        }
+
+        Some(ThemeCacheKey {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            theme: serde_json::to_value(&self.config).ok()?,
+            effects: serde_json::to_value(&self.effects).ok()?,
+            files: paths.into_iter().map(|path| {
+                let mtime = cache::mtime(&path);
+                (path, mtime)
+            }).collect(),
+        })
+    }
+
+    /// The cached theme and the variant it was resolved for
+    fn read_cached_theme(key: &ThemeCacheKey) -> Option<(StartupTheme, String)> {
+        let cached = cache::read::<CachedTheme>(THEME_CACHE_FILE)?;
+        if &cached.key != key {
+            return None;
+        }
+        let theme = StartupTheme {
+            colors: ThemeColors::from_cached(cached.colors)?,
+            css: cached.css,
+            command_pending: cached.command_pending,
+            cached_scheme: None,
+        };
+        Some((theme, cached.scheme))
+    }
+
+    fn write_cached_theme(&self, key: ThemeCacheKey, theme: &StartupTheme) {
+        let entry = CachedTheme {
+            key,
+            scheme: self.resolve_scheme().to_string(),
+            colors: theme.colors.to_cached(),
+            css: theme.css.clone(),
+            command_pending: theme.command_pending,
+        };
+        if let Err(e) = cache::write(THEME_CACHE_FILE, &entry) {
+            log::debug!("Failed to cache theme: {}", e);
+        }
+    }
+
+    /// A copy that resolves with `settings` instead of reading them itself.
+    ///
+    /// Off the GTK main thread only `settings.ini` can be read, which misses
+    /// changes made at runtime, so threads get the live values this way.
+    fn with_gtk_settings(&self, settings: system::GtkThemeSettings) -> Self {
+        Self { gtk_settings: Some(settings), ..self.resolving() }
+    }
+
+    /// A copy for one resolution, which asks the desktop for its settings
+    /// the first time they are needed and then reuses the answer
+    fn resolving(&self) -> Self {
+        Self { desktop: OnceLock::new(), ..self.clone() }
+    }
+
+    fn desktop(&self) -> &system::Desktop {
+        self.desktop.get_or_init(|| {
+            system::Desktop::read(self.gtk_settings.clone().unwrap_or_else(system::gtk_theme_settings))
+        })
+    }
+
+    /// Apply the contrast settings to freshly resolved colors
+    fn finish(&self, mut colors: ThemeColors) -> ThemeColors {
+        if self.config.auto_contrast || self.config.high_contrast {
+            contrast::enforce(&mut colors, self.contrast_requirements(), self.config.high_contrast);
+        }
+        colors
+    }
+
+    /// Minimum ratios from `min_contrast`, raised to AAA in high-contrast mode
+    fn contrast_requirements(&self) -> contrast::Requirements {
+        let text = self.config.min_contrast.unwrap_or(contrast::AA_TEXT);
+        if self.config.high_contrast {
+            contrast::Requirements { text: text.max(contrast::AAA_TEXT), ui: contrast::AA_TEXT }
+        } else {
+            contrast::Requirements { text, ui: contrast::AA_UI }
+        }
+    }
+
+    /// Color pairs in `colors` below the configured contrast minimum
+    pub fn contrast_issues(&self, colors: &ThemeColors) -> Vec<ContrastIssue> {
+        contrast::check(colors, self.contrast_requirements())
+    }
+
+    fn get_source_colors(&self, source: SourceKind) -> Result<ThemeColors> {
+        match source {
+            SourceKind::Manual => self.get_manual_colors(),
+            SourceKind::System => self.get_system_colors(),
+            SourceKind::File => self.get_file_colors(),
+            SourceKind::Command => self.get_command_colors(),
+            SourceKind::Seed => self.get_seed_colors(),
+            SourceKind::Wallpaper => self.get_wallpaper_colors(),
+            SourceKind::Base16 => self.get_base16_colors(),
+            SourceKind::Preset => self.get_preset_colors(),
+            SourceKind::Xresources | SourceKind::Kitty | SourceKind::Foot | SourceKind::Alacritty => {
+                self.get_terminal_colors(source.as_str())
+            }
         }
     }
 
     fn get_manual_colors(&self) -> Result<ThemeColors> {
         let colors = self.config.manual_colors.as_ref()
             .ok_or_else(|| anyhow!("Manual colors not configured"))?;
-        
-        Ok(ThemeColors {
-            background: colors.background.clone(),
-            primary: colors.primary.clone(),
-            secondary: colors.secondary.clone(),
-            text: colors.text.clone(),
-            danger: colors.danger.clone(),
-        })
+
+        let mut roles = BTreeMap::new();
+        let mut keys = BTreeMap::new();
+        for (role, value) in colors.roles() {
+            let key = format!("manual_colors.{}", role);
+            roles.insert(role.to_string(), parse_color(value, "manual", &key)?);
+            keys.insert(role.to_string(), key);
+        }
+        Ok(ThemeColors::from_roles(roles).with_keys(keys, |role| vec![format!("manual_colors.{}", role)]))
     }
 
     fn get_system_colors(&self) -> Result<ThemeColors> {
-        // Try to get colors from GTK theme
-        // This is a simplified implementation - in practice you'd want to
-        // parse the actual GTK theme files or use GTK APIs
-        log::info!("Using system theme colors (fallback to defaults)");
-        
-        // Fallback to default colors for now
-        let default_colors = ManualColors::default();
-        Ok(ThemeColors {
-            background: default_colors.background,
-            primary: default_colors.primary,
-            secondary: default_colors.secondary,
-            text: default_colors.text,
-            danger: default_colors.danger,
-        })
+        let desktop = self.desktop();
+        let (appearance, gtk_settings) = (&desktop.appearance, &desktop.gtk);
+
+        let dark = match self.config.scheme {
+            Some(Scheme::Light) => false,
+            Some(Scheme::Dark | Scheme::Amoled) => true,
+            _ => desktop.prefers_dark(),
+        };
+
+        let named = gtk_settings.theme_name.as_deref()
+            .map(|theme_name| system::theme_named_colors(theme_name, dark))
+            .unwrap_or_default();
+        log::info!(
+            "Using system theme colors (theme: {}, {}, {} named colors)",
+            gtk_settings.theme_name.as_deref().unwrap_or("unknown"),
+            if dark { "dark" } else { "light" },
+            named.len(),
+        );
+
+        // libadwaita's stock palette, used for anything the theme doesn't define
+        let (background, text, danger) = if dark {
+            (Color::rgba(36, 36, 36, 0.8), Color::rgb(255, 255, 255), Color::rgb(255, 123, 99))
+        } else {
+            (Color::rgba(250, 250, 250, 0.8), Color::rgba(0, 0, 0, 0.8), Color::rgb(192, 28, 40))
+        };
+        // Theme files may use expressions we can't evaluate; skip those
+        let find = |keys: &[&str]| {
+            keys.iter()
+                .filter_map(|key| named.get(*key).map(|value| (key, value)))
+                .find_map(|(key, value)| match Color::parse(value) {
+                    Ok(color) => Some((key.to_string(), color)),
+                    Err(e) => {
+                        log::debug!("Ignoring GTK theme color '{}': {}", key, e);
+                        None
+                    }
+                })
+        };
+        let pick = |keys: &[&str], default: (String, Color)| find(keys).unwrap_or(default);
+        let stock = |color: Color| ("libadwaita palette".to_string(), color);
+
+        let primary = match appearance.accent_color.as_deref() {
+            Some(accent) => ("accent-color".to_string(), parse_color(accent, "system", "accent-color")?),
+            None => pick(&["accent_bg_color", "accent_color", "theme_selected_bg_color"], stock(Color::rgb(53, 132, 228))),
+        };
+
+        let mut found = BTreeMap::from([
+            ("background".to_string(), pick(&["window_bg_color", "theme_bg_color"], stock(background))),
+            ("secondary".to_string(), pick(&["accent_color", "link_color", "theme_selected_bg_color"], primary.clone())),
+            ("text".to_string(), pick(&["window_fg_color", "theme_fg_color"], stock(text))),
+            ("danger".to_string(), pick(&["destructive_bg_color", "error_color", "destructive_color"], stock(danger))),
+            ("primary".to_string(), primary),
+        ]);
+
+        // Only take the extra roles the theme actually defines; the rest are derived
+        const EXTRA_ROLES: &[(&str, &[&str])] = &[
+            ("surface", &["card_bg_color", "view_bg_color", "theme_base_color"]),
+            ("surface_variant", &["headerbar_bg_color", "insensitive_bg_color"]),
+            ("outline", &["borders", "unfocused_borders"]),
+            ("on_primary", &["accent_fg_color", "theme_selected_fg_color"]),
+            ("on_danger", &["destructive_fg_color"]),
+            ("dialog_background", &["dialog_bg_color", "popover_bg_color"]),
+        ];
+        for (role, keys) in EXTRA_ROLES {
+            if let Some(color) = find(keys) {
+                found.insert(role.to_string(), color);
+            }
+        }
+
+        let keys = found.iter().map(|(role, (key, _))| (role.clone(), key.clone())).collect();
+        let roles = found.into_iter().map(|(role, (_, color))| (role, color)).collect();
+        let looked_for = |role: &str| {
+            EXTRA_ROLES.iter()
+                .find(|(extra, _)| *extra == role)
+                .map(|(_, keys)| keys.iter().map(|key| key.to_string()).collect())
+                .unwrap_or_default()
+        };
+        Ok(ThemeColors::from_roles(roles).with_keys(keys, looked_for))
     }
 
     fn get_file_colors(&self) -> Result<ThemeColors> {
@@ -76,65 +859,334 @@ impl ThemeManager {
         }
 
         let content = std::fs::read_to_string(file_path)?;
+        let source = format!("file {}", file_path.display());
         
         // Try to parse as JSON first (for matugen)
         if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(&content) {
-            return self.parse_json_colors(&json_value);
+            return self.parse_json_colors(&json_value, &source);
         }
 
         // Try to parse as simple key-value pairs
-        self.parse_simple_colors(&content)
+        self.parse_simple_colors(&content, &source)
     }
 
-    fn parse_json_colors(&self, json: &serde_json::Value) -> Result<ThemeColors> {
+    fn parse_json_colors(&self, json: &serde_json::Value, source: &str) -> Result<ThemeColors> {
+        // Handle pywal format (~/.cache/wal/colors.json)
+        if Self::is_pywal_json(json) {
+            return self.parse_pywal_colors(json, source);
+        }
+
         // Handle matugen format
         if let Some(colors) = json.get("colors") {
-            if let Some(_primary) = colors.get("primary") {
-                return Ok(ThemeColors {
-                    background: self.extract_color(colors, &["surface", "background"], "rgba(30, 30, 46, 0.8)"),
-                    primary: self.extract_color(colors, &["primary"], "#89b4fa"),
-                    secondary: self.extract_color(colors, &["secondary", "tertiary"], "#74c7ec"),
-                    text: self.extract_color(colors, &["on_surface", "on_background", "text"], "#cdd6f4"),
-                    danger: self.extract_color(colors, &["error", "danger"], "#f38ba8"),
-                });
+            if Self::is_matugen_colors(colors) {
+                return self.parse_matugen_colors(colors, source);
             }
         }
 
         // Handle simple JSON format
-        Ok(ThemeColors {
-            background: self.extract_color(json, &["background"], "rgba(30, 30, 46, 0.8)"),
-            primary: self.extract_color(json, &["primary"], "#89b4fa"),
-            secondary: self.extract_color(json, &["secondary"], "#74c7ec"),
-            text: self.extract_color(json, &["text"], "#cdd6f4"),
-            danger: self.extract_color(json, &["danger"], "#f38ba8"),
-        })
+        let scheme = self.resolve_scheme();
+        let mut roles = BTreeMap::new();
+        let mut keys = BTreeMap::new();
+        for role in SEMANTIC_ROLES {
+            if let Some((color, key)) = self.extract_color(json, role, source, scheme)? {
+                roles.insert(role.to_string(), color);
+                keys.insert(role.to_string(), key);
+            }
+        }
+        Ok(ThemeColors::from_roles(roles).with_keys(keys, |role| vec![role.to_string()]))
+    }
+
+    /// Resolve the configured scheme to the variant name used in theme files.
+    fn resolve_scheme(&self) -> &'static str {
+        match self.config.scheme.unwrap_or(Scheme::Auto) {
+            Scheme::Dark => "dark",
+            Scheme::Light => "light",
+            Scheme::Amoled => "amoled",
+            Scheme::Auto => if self.desktop().prefers_dark() { "dark" } else { "light" },
+        }
+    }
+
+    fn is_matugen_colors(colors: &serde_json::Value) -> bool {
+        colors.get("primary").is_some()
+            || ["dark", "light", "amoled"].iter()
+                .any(|scheme| colors.get(scheme).and_then(|table| table.get("primary")).is_some())
     }
 
-    fn extract_color(&self, json: &serde_json::Value, keys: &[&str], default: &str) -> String {
-        for key in keys {
-            if let Some(value) = json.get(key) {
-                if let Some(color_str) = value.as_str() {
-                    return color_str.to_string();
+    fn parse_matugen_colors(&self, colors: &serde_json::Value, source: &str) -> Result<ThemeColors> {
+        let scheme = self.resolve_scheme();
+        log::debug!("Parsing matugen colors using the {} scheme", scheme);
+
+        // Layout is either colors.<scheme>.<role> or colors.<role>[.<scheme>]
+        let scheme_table = scheme_candidates(scheme).iter()
+            .filter_map(|candidate| colors.get(candidate).map(|table| (candidate, table)))
+            .find(|(_, table)| table.is_object());
+
+        let mut roles = BTreeMap::new();
+        let mut keys = BTreeMap::new();
+        for role in MATERIAL_ROLES {
+            let found = match scheme_table.and_then(|(name, table)| table.get(role).map(|value| (name, value))) {
+                Some((name, value)) => Some((format!("colors.{}.{}", name, role), value)),
+                None => colors.get(role).map(|value| (format!("colors.{}", role), value)),
+            };
+            if let Some((key, value)) = found {
+                if let Some((color_str, path)) = Self::color_value(value, scheme) {
+                    let key = format!("{}{}", key, path);
+                    roles.insert(role.to_string(), parse_color(&color_str, source, &key)?);
+                    keys.insert(role.to_string(), key);
                 }
-                // Handle nested objects (like matugen's hex/rgb structure)
-                if let Some(hex) = value.get("hex") {
-                    if let Some(hex_str) = hex.as_str() {
-                        return hex_str.to_string();
+            }
+        }
+
+        let table = match scheme_table {
+            Some((name, _)) => format!("colors.{}", name),
+            None => "colors".to_string(),
+        };
+        Ok(Self::colors_from_material(roles, keys, |role| format!("{}.{}", table, role)))
+    }
+
+    /// Build theme colors from a set of Material 3 roles, read from `keys`.
+    /// `key` names where a missing Material role would have been.
+    fn colors_from_material(
+        roles: BTreeMap<String, Color>,
+        keys: BTreeMap<String, String>,
+        key: impl Fn(&str) -> String,
+    ) -> ThemeColors {
+        let mut semantic = BTreeMap::new();
+        let mut semantic_keys = BTreeMap::new();
+        for (role, candidates) in MATERIAL_SEMANTICS {
+            if let Some((candidate, color)) = candidates.iter().find_map(|candidate| roles.get(*candidate).map(|color| (candidate, *color))) {
+                semantic.insert(role.to_string(), color);
+                semantic_keys.insert(role.to_string(), keys.get(*candidate).cloned().unwrap_or_else(|| candidate.to_string()));
+            }
+        }
+
+        let looked_for = |role: &str| {
+            MATERIAL_SEMANTICS.iter()
+                .find(|(semantic, _)| *semantic == role)
+                .map(|(_, candidates)| candidates.iter().map(|candidate| key(candidate)).collect())
+                .unwrap_or_default()
+        };
+        let mut colors = ThemeColors::from_roles(semantic).with_keys(semantic_keys, looked_for);
+        colors.roles = roles;
+        colors.material_keys = keys;
+        colors
+    }
+
+    fn get_seed_colors(&self) -> Result<ThemeColors> {
+        let seed = self.config.seed.as_deref()
+            .ok_or_else(|| anyhow!("Seed color not configured for seed theme source"))?;
+        let seed = parse_color(seed, "seed", "seed")?;
+        Ok(Self::colors_from_material(self.material_scheme(seed), BTreeMap::new(), str::to_string))
+    }
+
+    fn get_wallpaper_colors(&self) -> Result<ThemeColors> {
+        let path = match (&self.config.wallpaper, &self.config.wallpaper_command) {
+            (Some(path), _) => path.clone(),
+            (None, Some(command)) => wallpaper::wallpaper_from_command(command, self.command_timeout())?,
+            (None, None) => return Err(anyhow!("Wallpaper or wallpaper_command not configured for wallpaper theme source")),
+        };
+
+        let seed = wallpaper::seed_from_wallpaper(&path)?;
+        Ok(Self::colors_from_material(self.material_scheme(seed), BTreeMap::new(), str::to_string))
+    }
+
+    /// Generate the Material 3 roles for `seed` in the configured scheme
+    fn material_scheme(&self, seed: Color) -> BTreeMap<String, Color> {
+        let scheme = self.resolve_scheme();
+        log::debug!("Generating {} Material scheme from seed {}", scheme, seed);
+
+        let mut roles = material::scheme_from_seed(seed, scheme != "light");
+        if scheme == "amoled" {
+            for role in ["background", "surface", "surface_dim", "surface_container_lowest"] {
+                roles.insert(role.to_string(), Color::rgb(0, 0, 0));
+            }
+        }
+        roles
+    }
+
+    /// Read a color from a plain string, a `{hex: ...}` object or a per-scheme
+    /// object, along with where in `value` it was, e.g. `.dark.hex`
+    fn color_value(value: &serde_json::Value, scheme: &str) -> Option<(String, String)> {
+        if let Some(color_str) = value.as_str() {
+            return Some((color_str.to_string(), String::new()));
+        }
+
+        for key in ["hex", "color"] {
+            if let Some(color_str) = value.get(key).and_then(|hex| hex.as_str()) {
+                return Some((color_str.to_string(), format!(".{}", key)));
+            }
+        }
+
+        scheme_candidates(scheme).iter()
+            .filter_map(|candidate| value.get(candidate).map(|variant| (candidate, variant)))
+            .find_map(|(candidate, variant)| {
+                let (color_str, path) = Self::color_value(variant, scheme)?;
+                Some((color_str, format!(".{}{}", candidate, path)))
+            })
+    }
+
+    fn is_pywal_json(json: &serde_json::Value) -> bool {
+        json.get("special").is_some_and(|special| special.is_object())
+            && json.get("colors").and_then(|colors| colors.get("color0")).is_some()
+    }
+
+    fn parse_pywal_colors(&self, json: &serde_json::Value, source: &str) -> Result<ThemeColors> {
+        // Flatten special.* and colors.colorN into a single slot table
+        let mut slots = BTreeMap::new();
+        let mut sections = BTreeMap::new();
+        for section in ["special", "colors"] {
+            if let Some(entries) = json.get(section).and_then(|value| value.as_object()) {
+                for (slot, value) in entries {
+                    if let Some(color) = value.as_str() {
+                        slots.insert(slot.clone(), color.to_string());
+                        sections.insert(slot.clone(), section);
                     }
                 }
             }
         }
-        default.to_string()
+
+        let mut mapping = self.terminal_mapping();
+        if let Some(overrides) = &self.config.pywal_mapping {
+            mapping.extend(overrides.clone());
+        }
+
+        log::debug!("Parsing pywal colors with mapping: {:?}", mapping);
+        Self::colors_from_slots(&slots, &mapping, "pywal", source, |slot| {
+            format!("{}.{}", sections.get(slot).copied().unwrap_or("colors"), slot)
+        })
     }
 
-    fn parse_simple_colors(&self, content: &str) -> Result<ThemeColors> {
-        let mut colors = ThemeColors {
-            background: "rgba(30, 30, 46, 0.8)".to_string(),
-            primary: "#89b4fa".to_string(),
-            secondary: "#74c7ec".to_string(),
-            text: "#cdd6f4".to_string(),
-            danger: "#f38ba8".to_string(),
-        };
+    /// The default terminal slot mapping with `terminal_mapping` applied
+    fn terminal_mapping(&self) -> BTreeMap<String, String> {
+        let mut mapping: BTreeMap<String, String> = TERMINAL_DEFAULT_MAPPING.iter()
+            .map(|(role, slot)| (role.to_string(), slot.to_string()))
+            .collect();
+        if let Some(overrides) = &self.config.terminal_mapping {
+            mapping.extend(overrides.clone());
+        }
+        mapping
+    }
+
+    /// Read colors from an Xresources, kitty, foot or alacritty config
+    fn get_terminal_colors(&self, format: &str) -> Result<ThemeColors> {
+        let path = self.config.file_path.clone()
+            .or_else(|| terminal::default_path(format))
+            .ok_or_else(|| anyhow!("File path not configured for {} theme source", format))?;
+        let slots = terminal::read_slots(format, &path)?;
+
+        let mapping = self.terminal_mapping();
+        log::debug!("Parsing {} colors from {} with mapping: {:?}", format, path.display(), mapping);
+        Self::colors_from_slots(&slots, &mapping, format, &format!("{} {}", format, path.display()), str::to_string)
+    }
+
+    fn get_preset_colors(&self) -> Result<ThemeColors> {
+        let name = self.config.preset.as_deref()
+            .ok_or_else(|| anyhow!("Preset not configured for preset theme source"))?;
+        let preset = presets::find(name).ok_or_else(|| {
+            anyhow!("Unknown preset '{}' (available: {})", name, preset_names().collect::<Vec<_>>().join(", "))
+        })?;
+
+        let scheme = self.resolve_scheme();
+        let mut roles = preset.palette(scheme != "light").roles();
+        if scheme == "amoled" {
+            roles.insert("background".to_string(), Color::rgb(0, 0, 0));
+        }
+        Ok(ThemeColors::from_roles(roles))
+    }
+
+    /// Read a Base16/Base24 scheme (YAML or JSON) from `file_path`.
+    ///
+    /// Both the classic flat layout (`base00: "181818"`) and the tinted-theming
+    /// layout with a `palette` table are understood.
+    fn get_base16_colors(&self) -> Result<ThemeColors> {
+        let file_path = self.config.file_path.as_ref()
+            .ok_or_else(|| anyhow!("File path not configured for base16 theme source"))?;
+        let content = std::fs::read_to_string(file_path)
+            .map_err(|e| anyhow!("Failed to read base16 scheme {}: {}", file_path.display(), e))?;
+        let source = format!("base16 {}", file_path.display());
+
+        // YAML is a superset of JSON, so this handles both
+        let scheme: serde_json::Value = serde_yaml::from_str(&content)
+            .map_err(|e| anyhow!("Invalid base16 scheme {}: {}", file_path.display(), e))?;
+        let palette = scheme.get("palette").filter(|palette| palette.is_object()).unwrap_or(&scheme);
+
+        let slots: BTreeMap<String, String> = palette.as_object()
+            .ok_or_else(|| anyhow!("Base16 scheme {} is not a table of colors", file_path.display()))?
+            .iter()
+            .filter(|(slot, _)| Self::is_base16_slot(slot))
+            .filter_map(|(slot, value)| {
+                let color = match value {
+                    serde_json::Value::String(color) => color.clone(),
+                    // Unquoted all-digit hex such as `181818` is read back as a number
+                    serde_json::Value::Number(number) => format!("{:06}", number.as_u64()?),
+                    _ => return None,
+                };
+                Some((slot.to_lowercase(), color))
+            })
+            .collect();
+        if slots.is_empty() {
+            return Err(anyhow!("No base00-base0F colors found in {}", file_path.display()));
+        }
+
+        let mut mapping: BTreeMap<String, String> = BASE16_DEFAULT_MAPPING.iter()
+            .map(|(role, slot)| (role.to_string(), slot.to_string()))
+            .collect();
+        if let Some(overrides) = &self.config.base16_mapping {
+            mapping.extend(overrides.iter().map(|(role, slot)| (role.clone(), slot.to_lowercase())));
+        }
+
+        if let Some(name) = scheme.get("name").or_else(|| scheme.get("scheme")).and_then(|name| name.as_str()) {
+            log::debug!("Parsing base16 scheme '{}' with mapping: {:?}", name, mapping);
+        }
+        Self::colors_from_slots(&slots, &mapping, "base16", &source, str::to_string)
+    }
+
+    /// `base00`-`base0F`, plus the base24 extras `base10`-`base17`
+    fn is_base16_slot(slot: &str) -> bool {
+        slot.len() == 6
+            && slot.get(..4).is_some_and(|prefix| prefix.eq_ignore_ascii_case("base"))
+            && u8::from_str_radix(&slot[4..], 16).is_ok_and(|index| index <= 0x17)
+    }
+
+    /// Map palette slots to roles; `key` names where a slot was read from
+    fn colors_from_slots(
+        slots: &BTreeMap<String, String>,
+        mapping: &BTreeMap<String, String>,
+        format: &str,
+        source: &str,
+        key: impl Fn(&str) -> String,
+    ) -> Result<ThemeColors> {
+        let mut roles = BTreeMap::new();
+        let mut keys = BTreeMap::new();
+
+        for (role, slot) in mapping {
+            let value = slots.get(slot)
+                .ok_or_else(|| anyhow!("Unknown {} slot '{}' mapped to role '{}'", format, slot, role))?;
+            if !is_known_role(role) {
+                return Err(anyhow!("Unknown theme role '{}' in {} mapping", role, format));
+            }
+            roles.insert(role.clone(), parse_color(value, source, slot)?);
+            keys.insert(role.clone(), key(slot));
+        }
+
+        // Roles without a slot in the mapping aren't looked for at all
+        Ok(ThemeColors::from_roles(roles).with_keys(keys, |_| Vec::new()))
+    }
+
+    /// The color at `key` and the path it was read from, e.g. `primary.dark`
+    fn extract_color(&self, json: &serde_json::Value, key: &str, source: &str, scheme: &str) -> Result<Option<(Color, String)>> {
+        json.get(key)
+            .and_then(|value| Self::color_value(value, scheme))
+            .map(|(color_str, path)| {
+                let key = format!("{}{}", key, path);
+                Ok((parse_color(&color_str, source, &key)?, key))
+            })
+            .transpose()
+    }
+
+    fn parse_simple_colors(&self, content: &str, source: &str) -> Result<ThemeColors> {
+        let mut roles = BTreeMap::new();
+        let mut keys = BTreeMap::new();
 
         for line in content.lines() {
             let line = line.trim();
@@ -146,228 +1198,646 @@ impl ThemeManager {
                 let key = key.trim().to_lowercase();
                 let value = value.trim().trim_matches('"').trim_matches('\'');
 
-                match key.as_str() {
-                    "background" => colors.background = value.to_string(),
-                    "primary" => colors.primary = value.to_string(),
-                    "secondary" => colors.secondary = value.to_string(),
-                    "text" => colors.text = value.to_string(),
-                    "danger" => colors.danger = value.to_string(),
-                    _ => {}
+                if is_known_role(&key) {
+                    let color = parse_color(value, source, &key)?;
+                    keys.insert(key.clone(), key.clone());
+                    roles.insert(key, color);
                 }
             }
         }
 
-        Ok(colors)
+        Ok(ThemeColors::from_roles(roles).with_keys(keys, |role| vec![role.to_string()]))
     }
 
+    /// Run the theme command, falling back to its last good output if it fails
     fn get_command_colors(&self) -> Result<ThemeColors> {
+        match self.run_theme_command() {
+            Ok(colors) => Ok(colors),
+            Err(e) => match self.cached_command_colors() {
+                Some(colors) => {
+                    log::warn!("{}; using the last good theme command output", e);
+                    Ok(colors)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    fn run_theme_command(&self) -> Result<ThemeColors> {
         let command = self.config.command.as_ref()
             .ok_or_else(|| anyhow!("Command not configured for command theme source"))?;
 
         log::debug!("Executing theme command: {}", command);
-        
-        let output = Command::new("sh")
-            .arg("-c")
-            .arg(command)
-            .output()?;
+        let output = command::run(command, self.command_timeout())
+            .map_err(|e| anyhow!("Theme command failed: {}", e))?;
+        let colors = self.parse_command_output(&output)?;
 
-        if !output.status.success() {
-            return Err(anyhow!("Theme command failed: {}", String::from_utf8_lossy(&output.stderr)));
+        let entry = CachedCommand { command: command.clone(), output };
+        if let Err(e) = cache::write(COMMAND_CACHE_FILE, &entry) {
+            log::debug!("Failed to cache theme command output: {}", e);
         }
+        Ok(colors)
+    }
 
-        let stdout = String::from_utf8(output.stdout)?;
-        
+    /// The cached output of the configured command, parsed for the current scheme
+    fn cached_command_colors(&self) -> Option<ThemeColors> {
+        let command = self.config.command.as_ref()?;
+        let cached = cache::read::<CachedCommand>(COMMAND_CACHE_FILE)?;
+        if &cached.command != command {
+            return None;
+        }
+        self.parse_command_output(&cached.output)
+            .map_err(|e| log::debug!("Ignoring cached theme command output: {}", e))
+            .ok()
+    }
+
+    fn parse_command_output(&self, stdout: &str) -> Result<ThemeColors> {
         // Try to parse as JSON first
-        if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(&stdout) {
-            return self.parse_json_colors(&json_value);
+        if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(stdout) {
+            return self.parse_json_colors(&json_value, "command");
         }
 
         // Try to parse as simple format
-        self.parse_simple_colors(&stdout)
+        self.parse_simple_colors(stdout, "command")
+    }
+
+    fn command_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.command_timeout)
+    }
+
+    /// Run the theme command on a background thread and send its colors.
+    ///
+    /// Used after `get_startup_theme` rendered from a cache. Only the
+    /// command runs here; if it fails the startup colors are kept.
+    pub fn refresh_command_colors(&self, sender: Sender<ThemeUpdate>) {
+        let manager = self.with_gtk_settings(system::gtk_theme_settings());
+        std::thread::spawn(move || {
+            match manager.run_theme_command() {
+                Ok(colors) => {
+                    log::info!("Theme command finished, applying its colors");
+                    let colors = manager.finish(colors);
+                    // Start from these colors next time
+                    if let Some(key) = manager.cache_key() {
+                        let css = manager.generate_css(&colors);
+                        let theme = StartupTheme { colors: colors.clone(), css, command_pending: true, cached_scheme: None };
+                        manager.write_cached_theme(key, &theme);
+                    }
+                    let _ = sender.send(ThemeUpdate::Colors(Box::new(colors)));
+                }
+                Err(e) => log::warn!("{}; keeping the current colors", e),
+            }
+        });
     }
 
     pub fn generate_css(&self, colors: &ThemeColors) -> String {
-        format!(
-            r#"
-/* Futuristic Aurora Glass Cards Theme */
-window {{
-    background-color: transparent;
-    font-family: sans-serif;
-}}
-
-/* Semi-transparent background for glow effects and compositor blur */
-.departure-background {{
-    background: {background};
-}}
-
-/* Glassmorphic card buttons with theme-colored glow */
-.departure-button {{
-    background: rgba(255, 255, 255, 0.08);
-    border: 2px solid {primary};
-    border-radius: 16px;
-    color: {text};
-    font-weight: 700;
-    font-size: 11px;
-    letter-spacing: 1px;
-    text-transform: uppercase;
-    box-shadow: 
-        0 15px 35px rgba(0, 0, 0, 0.4),
-        0 5px 15px rgba(0, 0, 0, 0.3),
-        0 0 20px {primary},
-        0 0 40px {primary},
-        inset 0 1px 0 rgba(255, 255, 255, 0.2);
-    transition: all 300ms ease;
-    padding: 16px;
-    opacity: 0.85;
-}}
-
-/* Hover effects with enhanced theme-colored glow */
-.departure-button:hover {{
-    background: rgba(255, 255, 255, 0.15);
-    border-color: {secondary};
-    box-shadow: 
-        0 20px 40px rgba(0, 0, 0, 0.5),
-        0 8px 25px {primary},
-        0 0 30px {secondary},
-        0 0 60px {primary},
-        0 0 100px {secondary},
-        inset 0 1px 0 rgba(255, 255, 255, 0.3);
-    opacity: 1.0;
-    transform: translateY(-2px);
-}}
-
-/* Active state */
-.departure-button:active {{
-    transform: translateY(0px);
-    opacity: 0.8;
-}}
-
-/* Danger variant */
-.departure-button.danger {{
-    border-color: {danger};
-}}
-
-.departure-button.danger:hover {{
-    border-color: {danger};
-    box-shadow: 
-        0 20px 40px rgba(0, 0, 0, 0.5),
-        0 8px 25px {danger},
-        0 0 30px {danger},
-        0 0 60px {danger},
-        inset 0 1px 0 rgba(255, 255, 255, 0.3);
-}}
-
-/* Button text styling */
-.departure-button-text {{
-    font-size: 14px;
-    font-weight: 700;
-    color: {text};
-    text-shadow: 0 1px 3px rgba(0, 0, 0, 0.7);
-    opacity: 0.9;
-}}
-
-/* SVG icon styling - no filters for crispness */
-.departure-button image {{
-    opacity: 0.9;
-}}
-
-.departure-button:hover image {{
-    opacity: 1;
-}}
-
-.departure-button:hover .departure-button-text {{
-    opacity: 1;
-}}
-
-/* Fallback text styling */
-.departure-button-fallback {{
-    font-size: 48px;
-    font-weight: 900;
-    color: {text};
-    text-shadow: 0 2px 8px rgba(0, 0, 0, 0.5);
-}}
-
-/* Confirmation dialog */
-.departure-confirmation {{
-    background: rgba(0, 0, 0, 0.9);
-    color: {text};
-    border: 2px solid rgba(0, 245, 255, 0.4);
-    border-radius: 16px;
-    box-shadow: 0 20px 50px rgba(0, 0, 0, 0.7);
-    padding: 24px;
-}}
-
-.departure-confirmation button {{
-    background: rgba(255, 255, 255, 0.1);
-    color: {text};
-    border: 1px solid rgba(255, 255, 255, 0.3);
-    border-radius: 8px;
-    padding: 12px 20px;
-    margin: 8px;
-    font-weight: 600;
-}}
-
-.departure-confirmation button:hover {{
-    background: rgba(255, 255, 255, 0.2);
-    border-color: rgba(0, 245, 255, 0.5);
-}}
-
-.departure-confirmation button.danger {{
-    border-color: rgba(255, 107, 107, 0.5);
-}}
-
-.departure-confirmation button.danger:hover {{
-    border-color: rgba(255, 107, 107, 0.8);
-}}
-
-/* Simple animations */
-@keyframes slideIn {{
-    from {{ 
-        opacity: 0; 
-        transform: translateY(20px);
-    }}
-    to {{ 
-        opacity: 1; 
-        transform: translateY(0);
-    }}
-}}
-
-/* Apply animations */
-.departure-button {{
-    animation: slideIn 400ms ease-out;
-}}
-
-/* Staggered animation delays */
-.departure-button:nth-child(1) {{ animation-delay: 0ms; }}
-.departure-button:nth-child(2) {{ animation-delay: 80ms; }}
-.departure-button:nth-child(3) {{ animation-delay: 160ms; }}
-.departure-button:nth-child(4) {{ animation-delay: 240ms; }}
-.departure-button:nth-child(5) {{ animation-delay: 320ms; }}
-"#,
-            text = colors.text,
-            background = colors.background,
-            primary = colors.primary,
-            secondary = colors.secondary,
-            danger = colors.danger,
-        )
-    }
-
-    pub fn start_file_watcher(&self) -> Result<()> {
-        if !self.config.watch_file {
+        let mut colors = colors.clone();
+        // Without compositor blur a see-through backdrop just shows the desktop
+        if !self.effects.blur {
+            colors.background = colors.background.with_alpha(1.0);
+        }
+        if self.config.high_contrast {
+            // Translucency would put unknown desktop content behind the text
+            colors.background = colors.background.with_alpha(1.0);
+            contrast::enforce(&mut colors, self.contrast_requirements(), true);
+        }
+        let colors = &colors;
+
+        // Expose every role as a named color for custom styling
+        let mut css: String = colors.named_colors().iter()
+            .map(|(role, value)| format!("@define-color {} {};\n", role, value))
+            .collect();
+        let variables = self.template_variables(colors);
+
+        if let Some(path) = &self.config.css_template {
+            let rendered = std::fs::read_to_string(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| template::render(&content, &variables));
+            match rendered {
+                Ok(rendered) => {
+                    css.push('\n');
+                    css.push_str(&rendered);
+                    return css;
+                }
+                Err(e) => log::warn!("Failed to render CSS template {}, using the built-in one: {}", path.display(), e),
+            }
+        }
+
+        let mut sections = vec![BASE_TEMPLATE];
+        if self.effects.hover_effects {
+            sections.push(HOVER_TEMPLATE);
+        }
+        if self.animated() {
+            sections.push(ANIMATION_TEMPLATE);
+        }
+
+        for section in sections {
+            css.push('\n');
+            css.push_str(&template::render(section, &variables).expect("built-in templates only use known placeholders"));
+        }
+        css
+    }
+
+    /// Read the user stylesheet, if one is configured.
+    pub fn user_stylesheet(&self) -> Option<String> {
+        let path = self.config.user_stylesheet.as_ref()?;
+        match std::fs::read_to_string(path) {
+            Ok(css) => Some(css),
+            Err(e) => {
+                log::warn!("Failed to read user stylesheet {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn animated(&self) -> bool {
+        self.effects.animations && self.effects.transition_duration > 0
+    }
+
+    /// Placeholder values for CSS templates: every color role plus the effect settings
+    fn template_variables(&self, colors: &ThemeColors) -> template::Variables {
+        use template::Value;
+
+        let mut variables: template::Variables = colors.named_colors().into_iter()
+            .map(|(role, value)| (role, Value::Color(value)))
+            .collect();
+
+        let duration = self.effects.transition_duration;
+        let (transition, animation) = if self.animated() {
+            (format!("all {}ms ease", duration), format!("slideIn {}ms ease-out", duration))
+        } else {
+            ("none".to_string(), "none".to_string())
+        };
+        variables.insert("transition".to_string(), Value::Text(transition));
+        variables.insert("animation".to_string(), Value::Text(animation));
+        variables.insert("transition_duration".to_string(), Value::Text(format!("{}ms", duration)));
+
+        // Stagger the buttons by a fraction of the entrance animation
+        for i in 0..STAGGERED_BUTTONS {
+            variables.insert(format!("stagger_{}", i + 1), Value::Text(format!("{}ms", i * duration / STAGGERED_BUTTONS)));
+        }
+
+        variables
+    }
+
+    /// Whether the resolved colors depend on the desktop's appearance settings
+    fn follows_desktop(&self) -> bool {
+        let sources = self.config.source.sources();
+        // The system source also follows the accent color, whatever the scheme
+        sources.contains(&SourceKind::System)
+            || (matches!(self.config.scheme, None | Some(Scheme::Auto))
+                && sources.iter().any(|&source| has_scheme_variants(source)))
+    }
+
+    /// Re-resolve colors when the desktop switches between dark and light.
+    ///
+    /// Listens to the settings portal and GTK settings, so this must be called
+    /// on the GTK main thread. Colors are resolved on a worker thread, since
+    /// commands and wallpapers can take a while, and sent like file changes are.
+    pub fn start_scheme_watcher(&self, sender: Sender<ThemeUpdate>) -> Result<()> {
+        if !self.follows_desktop() {
             return Ok(());
         }
 
-        let file_path = self.config.file_path.as_ref()
-            .ok_or_else(|| anyhow!("File path not configured for file watching"))?;
+        let manager = self.clone();
+        // Only the latest change is sent, in case an earlier one finishes after it
+        let latest = Arc::new(AtomicUsize::new(0));
+        let refresh = std::rc::Rc::new(move |gtk_settings: system::GtkThemeSettings| {
+            log::info!("Desktop appearance changed, reloading colors");
+            let generation = latest.fetch_add(1, Ordering::SeqCst) + 1;
+            let manager = manager.with_gtk_settings(gtk_settings);
+            let (sender, latest) = (sender.clone(), latest.clone());
+            std::thread::spawn(move || {
+                let colors = manager.explain().colors;
+                if latest.load(Ordering::SeqCst) == generation {
+                    // The window is gone if nobody is listening any more
+                    let _ = sender.send(ThemeUpdate::Colors(Box::new(colors)));
+                } else {
+                    log::debug!("Dropping colors for a superseded appearance change");
+                }
+            });
+        });
+
+        // Only the system source uses the accent color
+        let uses_accent = self.config.source.sources().contains(&SourceKind::System);
+        let on_gtk_change = refresh.clone();
+        system::watch_gtk_theme_settings(move |gtk_settings| on_gtk_change(gtk_settings));
+        system::watch_portal_appearance(move |key| {
+            if key == "color-scheme" || (key == "accent-color" && uses_accent) {
+                // Still on the main thread, so these are the live settings
+                refresh(system::gtk_theme_settings());
+            }
+        })?;
 
-        let (tx, _rx) = channel();
+        log::info!("Following the desktop's dark/light preference");
+        Ok(())
+    }
+
+    /// Watch the theme file, CSS template and user stylesheet and report changes.
+    ///
+    /// The theme file is only watched when `watch_file` is set. The watcher
+    /// runs on its own thread and stops once `sender` is disconnected.
+    pub fn start_watcher(&self, sender: Sender<ThemeUpdate>) -> Result<()> {
+        let mut targets = Vec::new();
+        if self.config.watch_file {
+            let file_path = self.config.file_path.clone()
+                .ok_or_else(|| anyhow!("File path not configured for file watching"))?;
+            targets.push((WatchTarget::Theme, file_path));
+        }
+        if let Some(path) = &self.config.css_template {
+            targets.push((WatchTarget::Template, path.clone()));
+        }
+        if let Some(path) = &self.config.user_stylesheet {
+            targets.push((WatchTarget::Stylesheet, path.clone()));
+        }
+        if targets.is_empty() {
+            return Ok(());
+        }
+
+        let (tx, rx) = channel();
         let mut watcher = RecommendedWatcher::new(tx, notify::Config::default())?;
-        watcher.watch(file_path, RecursiveMode::NonRecursive)?;
+        let mut watched_dirs = Vec::new();
+        for (_, path) in &mut targets {
+            if path.is_relative() {
+                *path = std::env::current_dir()?.join(&*path);
+            }
+            // Watch the parent directory rather than the file itself: editors and
+            // theme generators that save via rename-over would otherwise leave us
+            // watching a deleted inode.
+            let watch_dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+            if !watched_dirs.contains(&watch_dir) {
+                watcher.watch(&watch_dir, RecursiveMode::NonRecursive)?;
+                watched_dirs.push(watch_dir);
+            }
+            log::info!("Started watching {}", path.display());
+        }
+
+        let manager = self.clone();
+        std::thread::spawn(move || {
+            // The watcher stops delivering events once dropped, so keep it on this thread
+            let _watcher = watcher;
+
+            while let Ok(event) = rx.recv() {
+                let mut changed = Vec::new();
+                Self::record_changes(event, &targets, &mut changed);
+                if changed.is_empty() {
+                    continue;
+                }
+                // Debounce: wait until the burst of write/rename events settles
+                while let Ok(event) = rx.recv_timeout(WATCH_DEBOUNCE) {
+                    Self::record_changes(event, &targets, &mut changed);
+                }
+
+                for target in changed {
+                    let update = match target {
+                        WatchTarget::Theme => {
+                            log::info!("Theme file changed, reloading colors");
+                            match manager.get_colors() {
+                                Ok(colors) => ThemeUpdate::Colors(Box::new(colors)),
+                                Err(e) => {
+                                    log::warn!("Failed to reload theme colors: {}", e);
+                                    continue;
+                                }
+                            }
+                        }
+                        WatchTarget::Template => {
+                            log::info!("CSS template changed, regenerating stylesheet");
+                            ThemeUpdate::Template
+                        }
+                        WatchTarget::Stylesheet => {
+                            log::info!("User stylesheet changed, reloading it");
+                            ThemeUpdate::Stylesheet
+                        }
+                    };
+                    if sender.send(update).is_err() {
+                        log::debug!("Theme file watcher stopped");
+                        return;
+                    }
+                }
+            }
+
+            log::debug!("Theme file watcher stopped");
+        });
 
-        // In a real implementation, you'd want to handle this in a separate thread
-        // and notify the UI when the theme changes
-        log::info!("Started watching theme file: {}", file_path.display());
-        
         Ok(())
     }
+
+    fn record_changes(
+        event: notify::Result<notify::Event>,
+        targets: &[(WatchTarget, PathBuf)],
+        changed: &mut Vec<WatchTarget>,
+    ) {
+        match event {
+            Ok(event) => {
+                for (target, path) in targets {
+                    if Self::event_touches(&event, path) && !changed.contains(target) {
+                        changed.push(*target);
+                    }
+                }
+            }
+            Err(e) => log::warn!("Theme file watcher error: {}", e),
+        }
+    }
+
+    fn event_touches(event: &notify::Event, file_path: &Path) -> bool {
+        if event.kind.is_access() {
+            return false;
+        }
+        event.paths.iter().any(|path| path == file_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ThemeSource;
+
+    /// `wal -i forest.jpg`
+    const PYWAL_DARK: &str = r##"{
+    "wallpaper": "/home/user/Pictures/Wallpapers/forest.jpg",
+    "alpha": "100",
+
+    "special": {
+        "background": "#0b0d0c",
+        "foreground": "#c1c2c2",
+        "cursor": "#c1c2c2"
+    },
+    "colors": {
+        "color0": "#0b0d0c",
+        "color1": "#47583F",
+        "color2": "#4E6147",
+        "color3": "#5B6F4F",
+        "color4": "#6B7C59",
+        "color5": "#7B8862",
+        "color6": "#8F9A73",
+        "color7": "#c1c2c2",
+        "color8": "#878787",
+        "color9": "#47583F",
+        "color10": "#4E6147",
+        "color11": "#5B6F4F",
+        "color12": "#6B7C59",
+        "color13": "#7B8862",
+        "color14": "#8F9A73",
+        "color15": "#c1c2c2"
+    }
+}
+"##;
+
+    /// `wal -l -i beach.jpg`
+    const PYWAL_LIGHT: &str = r##"{
+    "wallpaper": "/home/user/Pictures/Wallpapers/beach.jpg",
+    "alpha": "100",
+
+    "special": {
+        "background": "#e7e3df",
+        "foreground": "#3a3430",
+        "cursor": "#3a3430"
+    },
+    "colors": {
+        "color0": "#e7e3df",
+        "color1": "#A1764D",
+        "color2": "#5E8199",
+        "color3": "#79869A",
+        "color4": "#B08B63",
+        "color5": "#6C93AD",
+        "color6": "#92A2B2",
+        "color7": "#3a3430",
+        "color8": "#a19e9b",
+        "color9": "#A1764D",
+        "color10": "#5E8199",
+        "color11": "#79869A",
+        "color12": "#B08B63",
+        "color13": "#6C93AD",
+        "color14": "#92A2B2",
+        "color15": "#3a3430"
+    }
+}
+"##;
+
+    /// `matugen image forest.jpg --json hex`
+    const MATUGEN: &str = r##"{
+  "colors": {
+    "background": { "dark": "#11140f", "default": "#11140f", "light": "#f9faf0" },
+    "error": { "dark": "#ffb4ab", "default": "#ffb4ab", "light": "#ba1a1a" },
+    "on_primary": { "dark": "#1a3705", "default": "#1a3705", "light": "#ffffff" },
+    "on_surface": { "dark": "#e1e4d9", "default": "#e1e4d9", "light": "#191d16" },
+    "primary": { "dark": "#a5d395", "default": "#a5d395", "light": "#3e6837" },
+    "secondary": { "dark": "#bccbb2", "default": "#bccbb2", "light": "#54634d" },
+    "surface": { "dark": "#11140f", "default": "#11140f", "light": "#f9faf0" }
+  },
+  "image": "/home/user/Pictures/Wallpapers/forest.jpg",
+  "mode": "dark"
+}
+"##;
+
+    fn manager(config: ThemeConfig) -> ThemeManager {
+        // A fixed scheme keeps the tests off the settings portal
+        let config = ThemeConfig { scheme: Some(Scheme::Dark), ..config };
+        ThemeManager::new(config, EffectsConfig::default()).unwrap()
+    }
+
+    fn parse(manager: &ThemeManager, content: &str) -> ThemeColors {
+        let json = serde_json::from_str(content).unwrap();
+        manager.parse_json_colors(&json, "test").unwrap()
+    }
+
+    fn color(value: &str) -> Color {
+        Color::parse(value).unwrap()
+    }
+
+    #[test]
+    fn pywal_dark_maps_special_and_palette_slots() {
+        let colors = parse(&manager(ThemeConfig::default()), PYWAL_DARK);
+        assert_eq!(colors.background, color("#0b0d0c"));
+        assert_eq!(colors.text, color("#c1c2c2"));
+        assert_eq!(colors.primary, color("#6B7C59"));
+        assert_eq!(colors.secondary, color("#8F9A73"));
+        assert_eq!(colors.danger, color("#47583F"));
+        assert_eq!(colors.origin("primary"), ColorOrigin::Source(Some("colors.color4".to_string())));
+        assert_eq!(colors.origin("background"), ColorOrigin::Source(Some("special.background".to_string())));
+        assert_eq!(colors.origin("surface"), ColorOrigin::Derived(Vec::new()));
+    }
+
+    #[test]
+    fn pywal_light_maps_special_and_palette_slots() {
+        let colors = parse(&manager(ThemeConfig::default()), PYWAL_LIGHT);
+        assert_eq!(colors.background, color("#e7e3df"));
+        assert_eq!(colors.text, color("#3a3430"));
+        assert_eq!(colors.primary, color("#B08B63"));
+        assert_eq!(colors.secondary, color("#92A2B2"));
+        assert_eq!(colors.danger, color("#A1764D"));
+    }
+
+    #[test]
+    fn pywal_mapping_overrides_default_and_terminal_mapping() {
+        let config = ThemeConfig {
+            terminal_mapping: Some(BTreeMap::from([("primary".to_string(), "color5".to_string())])),
+            pywal_mapping: Some(BTreeMap::from([
+                ("primary".to_string(), "color2".to_string()),
+                ("danger".to_string(), "color9".to_string()),
+                ("surface".to_string(), "color8".to_string()),
+            ])),
+            ..ThemeConfig::default()
+        };
+        let colors = parse(&manager(config), PYWAL_DARK);
+        assert_eq!(colors.primary, color("#4E6147"));
+        assert_eq!(colors.danger, color("#47583F"));
+        assert_eq!(colors.surface, color("#878787"));
+        assert_eq!(colors.origin("surface"), ColorOrigin::Source(Some("colors.color8".to_string())));
+        // Roles the mapping doesn't mention keep their defaults
+        assert_eq!(colors.secondary, color("#8F9A73"));
+    }
+
+    #[test]
+    fn pywal_mapping_to_a_missing_slot_is_an_error() {
+        let config = ThemeConfig {
+            pywal_mapping: Some(BTreeMap::from([("primary".to_string(), "color16".to_string())])),
+            ..ThemeConfig::default()
+        };
+        let json = serde_json::from_str(PYWAL_DARK).unwrap();
+        assert!(manager(config).parse_json_colors(&json, "test").is_err());
+    }
+
+    #[test]
+    fn matugen_is_not_detected_as_pywal() {
+        let json: serde_json::Value = serde_json::from_str(MATUGEN).unwrap();
+        assert!(!ThemeManager::is_pywal_json(&json));
+        assert!(ThemeManager::is_pywal_json(&serde_json::from_str(PYWAL_DARK).unwrap()));
+
+        let colors = parse(&manager(ThemeConfig::default()), MATUGEN);
+        assert_eq!(colors.primary, color("#a5d395"));
+        assert_eq!(colors.text, color("#e1e4d9"));
+        assert_eq!(colors.roles.get("on_primary"), Some(&color("#1a3705")));
+    }
+
+    #[test]
+    fn follows_desktop_only_with_dark_and_light_variants() {
+        let follows = |source: ThemeSource, scheme: Option<Scheme>| {
+            let config = ThemeConfig { source, scheme, ..ThemeConfig::default() };
+            ThemeManager::new(config, EffectsConfig::default()).unwrap().follows_desktop()
+        };
+        assert!(!follows(ThemeSource::Single(SourceKind::Manual), None));
+        assert!(!follows(ThemeSource::Single(SourceKind::Kitty), Some(Scheme::Auto)));
+        assert!(!follows(ThemeSource::Chain(vec![SourceKind::Base16, SourceKind::Manual]), None));
+        assert!(follows(ThemeSource::Chain(vec![SourceKind::Kitty, SourceKind::Seed]), None));
+        assert!(!follows(ThemeSource::Single(SourceKind::Seed), Some(Scheme::Dark)));
+        // The accent color can still change
+        assert!(follows(ThemeSource::Single(SourceKind::System), Some(Scheme::Light)));
+    }
+
+    #[test]
+    fn threads_resolve_with_the_gtk_settings_they_were_given() {
+        // settings.ini still says dark, as it does after switching GtkSettings to light at runtime
+        let dir = std::env::temp_dir().join(format!("departure-gtk-settings-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("gtk-4.0")).unwrap();
+        std::fs::write(
+            dir.join("gtk-4.0/settings.ini"),
+            "[Settings]\ngtk-theme-name=Adwaita-dark\ngtk-application-prefer-dark-theme=1\n",
+        ).unwrap();
+        // Nothing else reads these; the bus address keeps a real portal out of it
+        std::env::set_var("XDG_CONFIG_HOME", &dir);
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", format!("unix:path={}", dir.join("no-bus").display()));
+        std::env::remove_var("GTK_THEME");
+
+        let config = ThemeConfig { scheme: Some(Scheme::Auto), ..ThemeConfig::default() };
+        let manager = ThemeManager::new(config, EffectsConfig::default()).unwrap();
+        let from_file = manager.resolve_scheme();
+        let live = system::GtkThemeSettings { theme_name: Some("Adwaita".to_string()), prefer_dark: Some(false) };
+        let from_gtk = manager.with_gtk_settings(live).resolve_scheme();
+
+        std::env::remove_var("XDG_CONFIG_HOME");
+        std::env::remove_var("DBUS_SESSION_BUS_ADDRESS");
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(from_file, "dark");
+        assert_eq!(from_gtk, "light");
+    }
+
+    #[test]
+    fn without_blur_the_background_is_opaque_everywhere() {
+        let colors = ThemeColors { background: Color::rgba(0x1e, 0x1e, 0x2e, 0.8), ..ThemeColors::default() };
+        let css = |blur: bool| {
+            let effects = EffectsConfig { blur, ..EffectsConfig::default() };
+            ThemeManager::new(ThemeConfig::default(), effects).unwrap().generate_css(&colors)
+        };
+        let opaque = css(false);
+        assert!(opaque.contains("@define-color background #1e1e2e;"), "{}", opaque);
+        assert!(!opaque.contains("rgba(30, 30, 46, 0.8)"), "{}", opaque);
+        assert!(css(true).contains("@define-color background rgba(30, 30, 46, 0.8);"));
+    }
+
+    #[test]
+    fn buttons_are_staggered_across_the_animation() {
+        let effects = EffectsConfig { transition_duration: 200, ..EffectsConfig::default() };
+        let manager = ThemeManager::new(ThemeConfig::default(), effects).unwrap();
+        let variables = manager.template_variables(&ThemeColors::default());
+        let stagger = |i: u32| variables[&format!("stagger_{}", i)].to_string();
+        assert_eq!(stagger(1), "0ms");
+        assert_eq!(stagger(STAGGERED_BUTTONS), format!("{}ms", 200 * (STAGGERED_BUTTONS - 1) / STAGGERED_BUTTONS));
+    }
+
+    #[test]
+    fn cached_colors_read_back_unchanged() {
+        let dir = std::env::temp_dir().join(format!("departure-theme-cache-{}", std::process::id()));
+        // Nothing else reads XDG_CACHE_HOME
+        std::env::set_var("XDG_CACHE_HOME", &dir);
+
+        // Matugen's background and surface differ from the semantic roles of the same name
+        let manager = manager(ThemeConfig { high_contrast: true, ..ThemeConfig::default() });
+        let colors = manager.finish(parse(&manager, MATUGEN));
+        assert_ne!(colors.roles["surface"], colors.surface);
+        let theme = StartupTheme { css: manager.generate_css(&colors), colors, command_pending: false, cached_scheme: None };
+        manager.write_cached_theme(manager.cache_key().unwrap(), &theme);
+        let cached = ThemeManager::read_cached_theme(&manager.cache_key().unwrap());
+
+        std::env::remove_var("XDG_CACHE_HOME");
+        std::fs::remove_dir_all(&dir).unwrap();
+        let (cached, scheme) = cached.unwrap();
+        assert_eq!(cached.colors, theme.colors);
+        assert_eq!(cached.css, theme.css);
+        assert_eq!(scheme, "dark");
+        assert_eq!(cached.colors.origin("primary"), ColorOrigin::Source(Some("colors.primary.dark".to_string())));
+    }
+
+    #[test]
+    fn cache_key_covers_kitty_includes() {
+        let dir = std::env::temp_dir().join(format!("departure-cache-key-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("kitty.conf");
+        std::fs::write(&config, "include theme.conf\n").unwrap();
+        std::fs::write(dir.join("theme.conf"), "color4 #89b4fa\n").unwrap();
+
+        let manager = manager(ThemeConfig {
+            source: ThemeSource::Single(SourceKind::Kitty),
+            file_path: Some(config.clone()),
+            ..ThemeConfig::default()
+        });
+        let key = manager.cache_key().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let files: Vec<&PathBuf> = key.files.iter().map(|(path, _)| path).collect();
+        assert!(files.contains(&&dir.join("theme.conf")), "{:?}", files);
+    }
+
+    #[test]
+    fn matugen_origins_name_the_key_read() {
+        let colors = parse(&manager(ThemeConfig::default()), MATUGEN);
+        assert_eq!(colors.origin("primary"), ColorOrigin::Source(Some("colors.primary.dark".to_string())));
+        assert_eq!(colors.origin("background"), ColorOrigin::Source(Some("colors.surface.dark".to_string())));
+        assert_eq!(colors.material_origin("primary"), ColorOrigin::Source(Some("colors.primary.dark".to_string())));
+    }
+
+    #[test]
+    fn missing_roles_list_the_keys_looked_for() {
+        let colors = parse(&manager(ThemeConfig::default()), r##"{
+            "background": "#1e1e2e",
+            "primary": { "dark": { "hex": "#89b4fa" }, "light": "#1e66f5" }
+        }"##);
+        assert_eq!(colors.origin("primary"), ColorOrigin::Source(Some("primary.dark.hex".to_string())));
+        assert_eq!(colors.origin("text"), ColorOrigin::Default(vec!["text".to_string()]));
+        assert_eq!(colors.origin("surface"), ColorOrigin::Derived(vec!["surface".to_string()]));
+
+        let colors = parse(&manager(ThemeConfig::default()), r##"{ "colors": { "dark": { "primary": "#89b4fa" } } }"##);
+        assert_eq!(
+            colors.origin("surface_variant"),
+            ColorOrigin::Derived(vec!["colors.dark.surface_container_highest".to_string(), "colors.dark.surface_variant".to_string()]),
+        );
+    }
 }
@@ -0,0 +1,237 @@
+// Built-in theme presets
+//
+// Family names (`catppuccin`, `gruvbox`, ...) carry a dark and a light
+// variant; flavour names (`catppuccin-frappe`, `rose-pine-moon`, ...) pin one.
+
+use super::color::Color;
+use std::collections::BTreeMap;
+
+/// One variant of a preset; all values are opaque hex colors
+pub struct Palette {
+    pub background: &'static str,
+    pub surface: &'static str,
+    pub surface_variant: &'static str,
+    pub outline: &'static str,
+    pub text: &'static str,
+    pub primary: &'static str,
+    pub secondary: &'static str,
+    pub danger: &'static str,
+}
+
+pub struct Preset {
+    pub name: &'static str,
+    pub dark: Option<&'static Palette>,
+    pub light: Option<&'static Palette>,
+}
+
+/// Preset backgrounds are made translucent like the default palette so
+/// compositor blur shows through
+const BACKGROUND_ALPHA: f64 = 0.8;
+
+impl Palette {
+    /// The palette as theme roles, ready for `ThemeColors::from_roles`
+    pub fn roles(&self) -> BTreeMap<String, Color> {
+        let parse = |value: &str| Color::parse(value).expect("preset colors are valid");
+        BTreeMap::from([
+            ("background".to_string(), parse(self.background).with_alpha(BACKGROUND_ALPHA)),
+            ("surface".to_string(), parse(self.surface)),
+            ("surface_variant".to_string(), parse(self.surface_variant)),
+            ("outline".to_string(), parse(self.outline)),
+            ("text".to_string(), parse(self.text)),
+            ("primary".to_string(), parse(self.primary)),
+            ("secondary".to_string(), parse(self.secondary)),
+            ("danger".to_string(), parse(self.danger)),
+            ("on_primary".to_string(), parse(self.background)),
+            ("on_danger".to_string(), parse(self.background)),
+        ])
+    }
+}
+
+impl Preset {
+    /// The variant for a dark or light scheme, or the only one there is
+    pub fn palette(&self, dark: bool) -> &'static Palette {
+        let (preferred, other) = if dark { (self.dark, self.light) } else { (self.light, self.dark) };
+        preferred.or(other).expect("presets define at least one variant")
+    }
+}
+
+pub fn find(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name.eq_ignore_ascii_case(name))
+}
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    PRESETS.iter().map(|preset| preset.name)
+}
+
+const CATPPUCCIN_LATTE: Palette = Palette {
+    background: "#eff1f5",
+    surface: "#ccd0da",
+    surface_variant: "#bcc0cc",
+    outline: "#9ca0b0",
+    text: "#4c4f69",
+    primary: "#1e66f5",
+    secondary: "#209fb5",
+    danger: "#d20f39",
+};
+
+const CATPPUCCIN_FRAPPE: Palette = Palette {
+    background: "#303446",
+    surface: "#414559",
+    surface_variant: "#51576d",
+    outline: "#737994",
+    text: "#c6d0f5",
+    primary: "#8caaee",
+    secondary: "#85c1dc",
+    danger: "#e78284",
+};
+
+const CATPPUCCIN_MACCHIATO: Palette = Palette {
+    background: "#24273a",
+    surface: "#363a4f",
+    surface_variant: "#494d64",
+    outline: "#6e738d",
+    text: "#cad3f5",
+    primary: "#8aadf4",
+    secondary: "#7dc4e4",
+    danger: "#ed8796",
+};
+
+const CATPPUCCIN_MOCHA: Palette = Palette {
+    background: "#1e1e2e",
+    surface: "#313244",
+    surface_variant: "#45475a",
+    outline: "#6c7086",
+    text: "#cdd6f4",
+    primary: "#89b4fa",
+    secondary: "#74c7ec",
+    danger: "#f38ba8",
+};
+
+const NORD: Palette = Palette {
+    background: "#2e3440",
+    surface: "#3b4252",
+    surface_variant: "#434c5e",
+    outline: "#4c566a",
+    text: "#eceff4",
+    primary: "#88c0d0",
+    secondary: "#81a1c1",
+    danger: "#bf616a",
+};
+
+const GRUVBOX_DARK: Palette = Palette {
+    background: "#282828",
+    surface: "#3c3836",
+    surface_variant: "#504945",
+    outline: "#665c54",
+    text: "#ebdbb2",
+    primary: "#fabd2f",
+    secondary: "#8ec07c",
+    danger: "#fb4934",
+};
+
+const GRUVBOX_LIGHT: Palette = Palette {
+    background: "#fbf1c7",
+    surface: "#ebdbb2",
+    surface_variant: "#d5c4a1",
+    outline: "#bdae93",
+    text: "#3c3836",
+    primary: "#b57614",
+    secondary: "#427b58",
+    danger: "#9d0006",
+};
+
+const DRACULA: Palette = Palette {
+    background: "#282a36",
+    surface: "#343746",
+    surface_variant: "#44475a",
+    outline: "#6272a4",
+    text: "#f8f8f2",
+    primary: "#bd93f9",
+    secondary: "#ff79c6",
+    danger: "#ff5555",
+};
+
+const TOKYO_NIGHT: Palette = Palette {
+    background: "#1a1b26",
+    surface: "#24283b",
+    surface_variant: "#292e42",
+    outline: "#565f89",
+    text: "#c0caf5",
+    primary: "#7aa2f7",
+    secondary: "#bb9af7",
+    danger: "#f7768e",
+};
+
+const TOKYO_NIGHT_STORM: Palette = Palette {
+    background: "#24283b",
+    surface: "#1f2335",
+    surface_variant: "#292e42",
+    outline: "#565f89",
+    text: "#c0caf5",
+    primary: "#7aa2f7",
+    secondary: "#bb9af7",
+    danger: "#f7768e",
+};
+
+const TOKYO_NIGHT_DAY: Palette = Palette {
+    background: "#e1e2e7",
+    surface: "#d0d5e3",
+    surface_variant: "#c4c8da",
+    outline: "#848cb5",
+    text: "#3760bf",
+    primary: "#2e7de9",
+    secondary: "#9854f1",
+    danger: "#f52a65",
+};
+
+const ROSE_PINE: Palette = Palette {
+    background: "#191724",
+    surface: "#1f1d2e",
+    surface_variant: "#26233a",
+    outline: "#6e6a86",
+    text: "#e0def4",
+    primary: "#c4a7e7",
+    secondary: "#9ccfd8",
+    danger: "#eb6f92",
+};
+
+const ROSE_PINE_MOON: Palette = Palette {
+    background: "#232136",
+    surface: "#2a273f",
+    surface_variant: "#393552",
+    outline: "#6e6a86",
+    text: "#e0def4",
+    primary: "#c4a7e7",
+    secondary: "#9ccfd8",
+    danger: "#eb6f92",
+};
+
+const ROSE_PINE_DAWN: Palette = Palette {
+    background: "#faf4ed",
+    surface: "#fffaf3",
+    surface_variant: "#f2e9e1",
+    outline: "#9893a5",
+    text: "#575279",
+    primary: "#907aa9",
+    secondary: "#56949f",
+    danger: "#b4637a",
+};
+
+pub const PRESETS: &[Preset] = &[
+    Preset { name: "catppuccin", dark: Some(&CATPPUCCIN_MOCHA), light: Some(&CATPPUCCIN_LATTE) },
+    Preset { name: "catppuccin-latte", dark: None, light: Some(&CATPPUCCIN_LATTE) },
+    Preset { name: "catppuccin-frappe", dark: Some(&CATPPUCCIN_FRAPPE), light: None },
+    Preset { name: "catppuccin-macchiato", dark: Some(&CATPPUCCIN_MACCHIATO), light: None },
+    Preset { name: "catppuccin-mocha", dark: Some(&CATPPUCCIN_MOCHA), light: None },
+    Preset { name: "nord", dark: Some(&NORD), light: None },
+    Preset { name: "gruvbox", dark: Some(&GRUVBOX_DARK), light: Some(&GRUVBOX_LIGHT) },
+    Preset { name: "gruvbox-dark", dark: Some(&GRUVBOX_DARK), light: None },
+    Preset { name: "gruvbox-light", dark: None, light: Some(&GRUVBOX_LIGHT) },
+    Preset { name: "dracula", dark: Some(&DRACULA), light: None },
+    Preset { name: "tokyo-night", dark: Some(&TOKYO_NIGHT), light: Some(&TOKYO_NIGHT_DAY) },
+    Preset { name: "tokyo-night-storm", dark: Some(&TOKYO_NIGHT_STORM), light: None },
+    Preset { name: "tokyo-night-day", dark: None, light: Some(&TOKYO_NIGHT_DAY) },
+    Preset { name: "rose-pine", dark: Some(&ROSE_PINE), light: Some(&ROSE_PINE_DAWN) },
+    Preset { name: "rose-pine-moon", dark: Some(&ROSE_PINE_MOON), light: None },
+    Preset { name: "rose-pine-dawn", dark: None, light: Some(&ROSE_PINE_DAWN) },
+];
@@ -0,0 +1,525 @@
+// Wallpaper color extraction: Wu quantization refined with weighted k-means,
+// ranked by Material's scoring heuristics
+
+use super::color::Color;
+use super::material::{self, Hct};
+use std::collections::HashMap;
+
+const INDEX_BITS: u32 = 5;
+const SIDE_LENGTH: usize = 33;
+const TOTAL_SIZE: usize = SIDE_LENGTH * SIDE_LENGTH * SIDE_LENGTH;
+
+const KMEANS_ITERATIONS: usize = 10;
+
+/// Google Blue, used when no color in the image is suitable as a seed
+pub const FALLBACK_SEED: Color = Color::rgb(0x42, 0x85, 0xf4);
+
+/// Reduce `pixels` to at most `max_colors` representative colors with their populations.
+pub fn quantize(pixels: &[Color], max_colors: usize) -> Vec<(Color, u32)> {
+    let mut histogram: HashMap<(u8, u8, u8), u32> = HashMap::new();
+    for pixel in pixels.iter().filter(|pixel| pixel.is_opaque()) {
+        *histogram.entry((pixel.r, pixel.g, pixel.b)).or_insert(0) += 1;
+    }
+    if histogram.is_empty() {
+        return Vec::new();
+    }
+
+    let starting_clusters = Wu::new(&histogram).quantize(max_colors);
+    weighted_kmeans(&histogram, &starting_clusters)
+}
+
+fn index(r: usize, g: usize, b: usize) -> usize {
+    r * SIDE_LENGTH * SIDE_LENGTH + g * SIDE_LENGTH + b
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Cube {
+    r0: usize,
+    r1: usize,
+    g0: usize,
+    g1: usize,
+    b0: usize,
+    b1: usize,
+    volume: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Red,
+    Green,
+    Blue,
+}
+
+/// Xiaolin Wu's greedy orthogonal bipartition quantizer
+struct Wu {
+    weights: Vec<f64>,
+    moments_r: Vec<f64>,
+    moments_g: Vec<f64>,
+    moments_b: Vec<f64>,
+    moments: Vec<f64>,
+}
+
+impl Wu {
+    fn new(histogram: &HashMap<(u8, u8, u8), u32>) -> Self {
+        let mut wu = Self {
+            weights: vec![0.0; TOTAL_SIZE],
+            moments_r: vec![0.0; TOTAL_SIZE],
+            moments_g: vec![0.0; TOTAL_SIZE],
+            moments_b: vec![0.0; TOTAL_SIZE],
+            moments: vec![0.0; TOTAL_SIZE],
+        };
+
+        let bits_to_remove = 8 - INDEX_BITS;
+        for (&(r, g, b), &count) in histogram {
+            let i = index(
+                (r >> bits_to_remove) as usize + 1,
+                (g >> bits_to_remove) as usize + 1,
+                (b >> bits_to_remove) as usize + 1,
+            );
+            let (r, g, b, count) = (r as f64, g as f64, b as f64, count as f64);
+            wu.weights[i] += count;
+            wu.moments_r[i] += count * r;
+            wu.moments_g[i] += count * g;
+            wu.moments_b[i] += count * b;
+            wu.moments[i] += count * (r * r + g * g + b * b);
+        }
+
+        wu.compute_moments();
+        wu
+    }
+
+    /// Turn the histogram into cumulative moments so any box can be summed in O(1)
+    fn compute_moments(&mut self) {
+        for r in 1..SIDE_LENGTH {
+            let mut area = [0.0; SIDE_LENGTH];
+            let mut area_r = [0.0; SIDE_LENGTH];
+            let mut area_g = [0.0; SIDE_LENGTH];
+            let mut area_b = [0.0; SIDE_LENGTH];
+            let mut area2 = [0.0; SIDE_LENGTH];
+
+            for g in 1..SIDE_LENGTH {
+                let (mut line, mut line_r, mut line_g, mut line_b, mut line2) = (0.0, 0.0, 0.0, 0.0, 0.0);
+
+                for b in 1..SIDE_LENGTH {
+                    let i = index(r, g, b);
+                    line += self.weights[i];
+                    line_r += self.moments_r[i];
+                    line_g += self.moments_g[i];
+                    line_b += self.moments_b[i];
+                    line2 += self.moments[i];
+
+                    area[b] += line;
+                    area_r[b] += line_r;
+                    area_g[b] += line_g;
+                    area_b[b] += line_b;
+                    area2[b] += line2;
+
+                    let previous = index(r - 1, g, b);
+                    self.weights[i] = self.weights[previous] + area[b];
+                    self.moments_r[i] = self.moments_r[previous] + area_r[b];
+                    self.moments_g[i] = self.moments_g[previous] + area_g[b];
+                    self.moments_b[i] = self.moments_b[previous] + area_b[b];
+                    self.moments[i] = self.moments[previous] + area2[b];
+                }
+            }
+        }
+    }
+
+    fn quantize(&self, max_colors: usize) -> Vec<Color> {
+        let max_colors = max_colors.max(1);
+        let mut cubes = vec![Cube::default(); max_colors];
+        cubes[0] = Cube {
+            r1: SIDE_LENGTH - 1,
+            g1: SIDE_LENGTH - 1,
+            b1: SIDE_LENGTH - 1,
+            ..Cube::default()
+        };
+
+        let mut volume_variance = vec![0.0; max_colors];
+        let mut generated = max_colors;
+        let mut next = 0;
+        let mut i = 1;
+        while i < max_colors {
+            let (mut one, mut two) = (cubes[next], cubes[i]);
+            if self.cut(&mut one, &mut two) {
+                volume_variance[next] = if one.volume > 1 { self.variance(&one) } else { 0.0 };
+                volume_variance[i] = if two.volume > 1 { self.variance(&two) } else { 0.0 };
+                cubes[next] = one;
+                cubes[i] = two;
+            } else {
+                volume_variance[next] = 0.0;
+                i -= 1;
+            }
+
+            next = 0;
+            let mut max_variance = volume_variance[0];
+            for (j, variance) in volume_variance.iter().enumerate().take(i + 1).skip(1) {
+                if *variance > max_variance {
+                    max_variance = *variance;
+                    next = j;
+                }
+            }
+            if max_variance <= 0.0 {
+                generated = i + 1;
+                break;
+            }
+            i += 1;
+        }
+
+        cubes.iter()
+            .take(generated)
+            .filter_map(|cube| {
+                let weight = Self::volume(cube, &self.weights);
+                (weight > 0.0).then(|| {
+                    let channel = |moments: &[f64]| (Self::volume(cube, moments) / weight).round().clamp(0.0, 255.0) as u8;
+                    Color::rgb(channel(&self.moments_r), channel(&self.moments_g), channel(&self.moments_b))
+                })
+            })
+            .collect()
+    }
+
+    fn variance(&self, cube: &Cube) -> f64 {
+        let dr = Self::volume(cube, &self.moments_r);
+        let dg = Self::volume(cube, &self.moments_g);
+        let db = Self::volume(cube, &self.moments_b);
+        let xx = Self::volume(cube, &self.moments);
+        let hypotenuse = dr * dr + dg * dg + db * db;
+        xx - hypotenuse / Self::volume(cube, &self.weights)
+    }
+
+    fn cut(&self, one: &mut Cube, two: &mut Cube) -> bool {
+        let whole = [
+            Self::volume(one, &self.moments_r),
+            Self::volume(one, &self.moments_g),
+            Self::volume(one, &self.moments_b),
+            Self::volume(one, &self.weights),
+        ];
+
+        let (cut_r, max_r) = self.maximize(one, Direction::Red, one.r0 + 1, one.r1, whole);
+        let (cut_g, max_g) = self.maximize(one, Direction::Green, one.g0 + 1, one.g1, whole);
+        let (cut_b, max_b) = self.maximize(one, Direction::Blue, one.b0 + 1, one.b1, whole);
+
+        let (direction, cut) = if max_r >= max_g && max_r >= max_b {
+            match cut_r {
+                Some(cut) => (Direction::Red, cut),
+                None => return false,
+            }
+        } else if max_g >= max_r && max_g >= max_b {
+            (Direction::Green, cut_g.unwrap_or(one.g0))
+        } else {
+            (Direction::Blue, cut_b.unwrap_or(one.b0))
+        };
+
+        two.r1 = one.r1;
+        two.g1 = one.g1;
+        two.b1 = one.b1;
+
+        match direction {
+            Direction::Red => {
+                one.r1 = cut;
+                two.r0 = one.r1;
+                two.g0 = one.g0;
+                two.b0 = one.b0;
+            }
+            Direction::Green => {
+                one.g1 = cut;
+                two.r0 = one.r0;
+                two.g0 = one.g1;
+                two.b0 = one.b0;
+            }
+            Direction::Blue => {
+                one.b1 = cut;
+                two.r0 = one.r0;
+                two.g0 = one.g0;
+                two.b0 = one.b1;
+            }
+        }
+
+        one.volume = (one.r1 - one.r0) * (one.g1 - one.g0) * (one.b1 - one.b0);
+        two.volume = (two.r1 - two.r0) * (two.g1 - two.g0) * (two.b1 - two.b0);
+        true
+    }
+
+    fn maximize(&self, cube: &Cube, direction: Direction, first: usize, last: usize, whole: [f64; 4]) -> (Option<usize>, f64) {
+        let moments = [&self.moments_r, &self.moments_g, &self.moments_b, &self.weights];
+        let bottom = moments.map(|moment| Self::bottom(cube, direction, moment));
+
+        let mut max = 0.0;
+        let mut cut = None;
+        for position in first..last {
+            let half: Vec<f64> = moments.iter()
+                .zip(bottom)
+                .map(|(moment, bottom)| bottom + Self::top(cube, direction, position, moment))
+                .collect();
+            if half[3] == 0.0 {
+                continue;
+            }
+            let mut temp = (half[0] * half[0] + half[1] * half[1] + half[2] * half[2]) / half[3];
+
+            let rest: Vec<f64> = whole.iter().zip(&half).map(|(whole, half)| whole - half).collect();
+            if rest[3] == 0.0 {
+                continue;
+            }
+            temp += (rest[0] * rest[0] + rest[1] * rest[1] + rest[2] * rest[2]) / rest[3];
+
+            if temp > max {
+                max = temp;
+                cut = Some(position);
+            }
+        }
+
+        (cut, max)
+    }
+
+    fn volume(cube: &Cube, moment: &[f64]) -> f64 {
+        moment[index(cube.r1, cube.g1, cube.b1)]
+            - moment[index(cube.r1, cube.g1, cube.b0)]
+            - moment[index(cube.r1, cube.g0, cube.b1)]
+            + moment[index(cube.r1, cube.g0, cube.b0)]
+            - moment[index(cube.r0, cube.g1, cube.b1)]
+            + moment[index(cube.r0, cube.g1, cube.b0)]
+            + moment[index(cube.r0, cube.g0, cube.b1)]
+            - moment[index(cube.r0, cube.g0, cube.b0)]
+    }
+
+    fn bottom(cube: &Cube, direction: Direction, moment: &[f64]) -> f64 {
+        match direction {
+            Direction::Red => {
+                -moment[index(cube.r0, cube.g1, cube.b1)]
+                    + moment[index(cube.r0, cube.g1, cube.b0)]
+                    + moment[index(cube.r0, cube.g0, cube.b1)]
+                    - moment[index(cube.r0, cube.g0, cube.b0)]
+            }
+            Direction::Green => {
+                -moment[index(cube.r1, cube.g0, cube.b1)]
+                    + moment[index(cube.r1, cube.g0, cube.b0)]
+                    + moment[index(cube.r0, cube.g0, cube.b1)]
+                    - moment[index(cube.r0, cube.g0, cube.b0)]
+            }
+            Direction::Blue => {
+                -moment[index(cube.r1, cube.g1, cube.b0)]
+                    + moment[index(cube.r1, cube.g0, cube.b0)]
+                    + moment[index(cube.r0, cube.g1, cube.b0)]
+                    - moment[index(cube.r0, cube.g0, cube.b0)]
+            }
+        }
+    }
+
+    fn top(cube: &Cube, direction: Direction, position: usize, moment: &[f64]) -> f64 {
+        match direction {
+            Direction::Red => {
+                moment[index(position, cube.g1, cube.b1)]
+                    - moment[index(position, cube.g1, cube.b0)]
+                    - moment[index(position, cube.g0, cube.b1)]
+                    + moment[index(position, cube.g0, cube.b0)]
+            }
+            Direction::Green => {
+                moment[index(cube.r1, position, cube.b1)]
+                    - moment[index(cube.r1, position, cube.b0)]
+                    - moment[index(cube.r0, position, cube.b1)]
+                    + moment[index(cube.r0, position, cube.b0)]
+            }
+            Direction::Blue => {
+                moment[index(cube.r1, cube.g1, position)]
+                    - moment[index(cube.r1, cube.g0, position)]
+                    - moment[index(cube.r0, cube.g1, position)]
+                    + moment[index(cube.r0, cube.g0, position)]
+            }
+        }
+    }
+}
+
+fn lab_from_color(color: Color) -> [f64; 3] {
+    let [x, y, z] = material::xyz_from_color(color);
+    let fx = material::lab_f(x / material::WHITE_POINT[0]);
+    let fy = material::lab_f(y / material::WHITE_POINT[1]);
+    let fz = material::lab_f(z / material::WHITE_POINT[2]);
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn color_from_lab(lab: [f64; 3]) -> Color {
+    let fy = (lab[0] + 16.0) / 116.0;
+    let fx = lab[1] / 500.0 + fy;
+    let fz = fy - lab[2] / 200.0;
+    material::color_from_xyz([
+        material::lab_inv_f(fx) * material::WHITE_POINT[0],
+        material::lab_inv_f(fy) * material::WHITE_POINT[1],
+        material::lab_inv_f(fz) * material::WHITE_POINT[2],
+    ])
+}
+
+fn distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+/// Refine the Wu clusters with population-weighted k-means in L*a*b*
+fn weighted_kmeans(histogram: &HashMap<(u8, u8, u8), u32>, starting_clusters: &[Color]) -> Vec<(Color, u32)> {
+    let points: Vec<([f64; 3], u32)> = histogram.iter()
+        .map(|(&(r, g, b), &count)| (lab_from_color(Color::rgb(r, g, b)), count))
+        .collect();
+    let mut centroids: Vec<[f64; 3]> = starting_clusters.iter().map(|color| lab_from_color(*color)).collect();
+    if centroids.is_empty() {
+        return Vec::new();
+    }
+
+    let mut assignments = vec![0; points.len()];
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut changed = false;
+        for (point, assignment) in points.iter().zip(assignments.iter_mut()) {
+            let nearest = centroids.iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| distance(&point.0, a).total_cmp(&distance(&point.0, b)))
+                .map(|(i, _)| i)
+                .unwrap_or(0);
+            if nearest != *assignment {
+                *assignment = nearest;
+                changed = true;
+            }
+        }
+
+        let mut sums = vec![([0.0; 3], 0.0); centroids.len()];
+        for ((lab, count), assignment) in points.iter().zip(&assignments) {
+            let (sum, weight) = &mut sums[*assignment];
+            for channel in 0..3 {
+                sum[channel] += lab[channel] * *count as f64;
+            }
+            *weight += *count as f64;
+        }
+        for (centroid, (sum, weight)) in centroids.iter_mut().zip(&sums) {
+            if *weight > 0.0 {
+                *centroid = sum.map(|channel| channel / weight);
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    let mut populations = vec![0u32; centroids.len()];
+    for ((_, count), assignment) in points.iter().zip(&assignments) {
+        populations[*assignment] += count;
+    }
+
+    centroids.iter()
+        .zip(populations)
+        .filter(|(_, population)| *population > 0)
+        .map(|(lab, population)| (color_from_lab(*lab), population))
+        .collect()
+}
+
+/// Rank quantized colors by how well they'd work as a theme seed.
+///
+/// Favors colors whose hue family covers a large part of the image and whose
+/// chroma is close to Material's target; near-grays are discarded.
+pub fn score(colors: &[(Color, u32)]) -> Vec<Color> {
+    const TARGET_CHROMA: f64 = 48.0;
+    const WEIGHT_PROPORTION: f64 = 0.7;
+    const WEIGHT_CHROMA_ABOVE: f64 = 0.3;
+    const WEIGHT_CHROMA_BELOW: f64 = 0.1;
+    const CUTOFF_CHROMA: f64 = 5.0;
+    const CUTOFF_EXCITED_PROPORTION: f64 = 0.01;
+
+    let mut hue_population = [0.0; 360];
+    let mut total = 0.0;
+    let hcts: Vec<(Color, Hct)> = colors.iter().map(|(color, _)| (*color, Hct::from_color(*color))).collect();
+    for ((_, population), (_, hct)) in colors.iter().zip(&hcts) {
+        hue_population[(hct.hue.floor() as usize) % 360] += *population as f64;
+        total += *population as f64;
+    }
+    if total <= 0.0 {
+        return Vec::new();
+    }
+
+    // Share of the image within ±15° of each hue
+    let mut hue_excited_proportions = [0.0; 360];
+    for (hue, population) in hue_population.iter().enumerate() {
+        let proportion = population / total;
+        for offset in -14i32..16 {
+            let neighbor = (hue as i32 + offset).rem_euclid(360) as usize;
+            hue_excited_proportions[neighbor] += proportion;
+        }
+    }
+
+    let mut scored: Vec<(Color, Hct, f64)> = hcts.into_iter()
+        .filter_map(|(color, hct)| {
+            let proportion = hue_excited_proportions[(hct.hue.round() as usize) % 360];
+            if hct.chroma < CUTOFF_CHROMA || proportion <= CUTOFF_EXCITED_PROPORTION {
+                return None;
+            }
+            let proportion_score = proportion * 100.0 * WEIGHT_PROPORTION;
+            let chroma_weight = if hct.chroma < TARGET_CHROMA { WEIGHT_CHROMA_BELOW } else { WEIGHT_CHROMA_ABOVE };
+            let chroma_score = (hct.chroma - TARGET_CHROMA) * chroma_weight;
+            Some((color, hct, proportion_score + chroma_score))
+        })
+        .collect();
+    scored.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+    // Prefer a diverse set: drop colors too close in hue to a better one
+    let mut chosen: Vec<(Color, Hct)> = Vec::new();
+    for (color, hct, _) in scored {
+        let distinct = chosen.iter().all(|(_, other)| {
+            let difference = (hct.hue - other.hue).abs();
+            difference.min(360.0 - difference) >= 15.0
+        });
+        if distinct {
+            chosen.push((color, hct));
+        }
+    }
+
+    chosen.into_iter().map(|(color, _)| color).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(parts: &[(&str, usize)]) -> Vec<Color> {
+        parts.iter()
+            .flat_map(|(color, count)| std::iter::repeat_n(Color::parse(color).unwrap(), *count))
+            .collect()
+    }
+
+    fn populations(colors: &[(Color, u32)]) -> Vec<(String, u32)> {
+        let mut populations: Vec<(String, u32)> = colors.iter().map(|(color, count)| (color.to_string(), *count)).collect();
+        populations.sort();
+        populations
+    }
+
+    #[test]
+    fn two_colors_keep_their_counts() {
+        let colors = quantize(&image(&[("#3366ff", 150), ("#808080", 250)]), 128);
+        assert_eq!(populations(&colors), [("#3366ff".to_string(), 150), ("#808080".to_string(), 250)]);
+    }
+
+    #[test]
+    fn translucent_pixels_are_ignored() {
+        let mut pixels = image(&[("#3366ff", 10)]);
+        pixels.push(Color::rgba(255, 0, 0, 0.5));
+        assert_eq!(populations(&quantize(&pixels, 128)), [("#3366ff".to_string(), 10)]);
+    }
+
+    #[test]
+    fn most_chromatic_of_equal_shares_scores_first() {
+        let colors = quantize(&image(&[("#a08070", 200), ("#ff0000", 200)]), 128);
+        let ranked: Vec<String> = score(&colors).iter().map(Color::to_string).collect();
+        assert_eq!(ranked, ["#ff0000", "#a08070"]);
+    }
+
+    #[test]
+    fn greys_are_not_seeds() {
+        let colors = quantize(&image(&[("#202020", 100), ("#808080", 300), ("#f0f0f0", 50)]), 128);
+        assert_eq!(colors.iter().map(|(_, count)| count).sum::<u32>(), 450);
+        // Nothing to pick, so the wallpaper source falls back to FALLBACK_SEED
+        assert!(score(&colors).is_empty());
+
+        let colors = quantize(&image(&[("#3366ff", 150), ("#808080", 250)]), 128);
+        assert_eq!(score(&colors), [Color::parse("#3366ff").unwrap()]);
+    }
+
+    #[test]
+    fn empty_images_have_no_colors() {
+        assert!(quantize(&[], 128).is_empty());
+        assert!(score(&[]).is_empty());
+    }
+}
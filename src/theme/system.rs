@@ -0,0 +1,574 @@
+// System theme detection: XDG settings portal, GTK settings and GTK theme files
+
+use anyhow::{Result, anyhow};
+use gtk4::gio;
+use gtk4::glib;
+use gtk4::prelude::*;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+const PORTAL_BUS_NAME: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_OBJECT_PATH: &str = "/org/freedesktop/portal/desktop";
+const PORTAL_SETTINGS_INTERFACE: &str = "org.freedesktop.portal.Settings";
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+
+/// Don't let a wedged portal delay opening the menu
+const PORTAL_TIMEOUT_MS: i32 = 500;
+
+const UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+const SETTING_NOT_FOUND: &str = "org.freedesktop.portal.Error.NotFound";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScheme {
+    NoPreference,
+    PreferDark,
+    PreferLight,
+}
+
+/// Values read from the `org.freedesktop.appearance` portal namespace
+#[derive(Debug, Clone, Default)]
+pub struct PortalAppearance {
+    pub color_scheme: Option<ColorScheme>,
+    pub accent_color: Option<String>,
+}
+
+/// The subset of GTK settings departure cares about
+#[derive(Debug, Clone, Default)]
+pub struct GtkThemeSettings {
+    pub theme_name: Option<String>,
+    pub prefer_dark: Option<bool>,
+}
+
+/// The desktop's appearance settings at one point in time
+#[derive(Debug, Clone, Default)]
+pub struct Desktop {
+    pub appearance: PortalAppearance,
+    pub gtk: GtkThemeSettings,
+}
+
+impl Desktop {
+    /// Ask the portal, next to GTK settings the caller has read
+    pub fn read(gtk: GtkThemeSettings) -> Self {
+        let appearance = portal_appearance().unwrap_or_else(|e| {
+            log::debug!("Settings portal unavailable: {}", e);
+            PortalAppearance::default()
+        });
+        Self { appearance, gtk }
+    }
+
+    /// Best-effort guess at the dark/light preference
+    pub fn prefers_dark(&self) -> bool {
+        match self.appearance.color_scheme {
+            Some(ColorScheme::PreferDark) => return true,
+            Some(ColorScheme::PreferLight) => return false,
+            _ => {}
+        }
+
+        if let Ok(gtk_theme) = std::env::var("GTK_THEME") {
+            let gtk_theme = gtk_theme.to_lowercase();
+            if gtk_theme.ends_with(":light") {
+                return false;
+            }
+            if gtk_theme.contains("dark") {
+                return true;
+            }
+        }
+
+        if self.gtk.prefer_dark == Some(true) {
+            return true;
+        }
+        match &self.gtk.theme_name {
+            Some(theme_name) => theme_name.to_lowercase().contains("dark"),
+            None => self.gtk.prefer_dark.unwrap_or(true),
+        }
+    }
+}
+
+/// Read the appearance settings from the portal on the session bus.
+pub fn portal_appearance() -> Result<PortalAppearance> {
+    let connection = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE)?;
+    read_portal_appearance(&connection)
+}
+
+/// Read the appearance settings from the portal reachable through `connection`.
+///
+/// Taking the connection lets callers point this at a private bus, e.g. one
+/// running a mock portal.
+pub fn read_portal_appearance(connection: &gio::DBusConnection) -> Result<PortalAppearance> {
+    let color_scheme = match read_setting(connection, "color-scheme") {
+        Ok(value) => match value.get::<u32>() {
+            Some(1) => Some(ColorScheme::PreferDark),
+            Some(2) => Some(ColorScheme::PreferLight),
+            Some(_) => Some(ColorScheme::NoPreference),
+            None => None,
+        },
+        // Without a portal the accent color would only time out too
+        Err(e) if !portal_answered(&e) => return Err(e),
+        Err(e) => {
+            log::debug!("Portal color-scheme unavailable: {}", e);
+            None
+        }
+    };
+
+    let accent_color = match read_setting(connection, "accent-color") {
+        // Components outside 0..1 mean the accent color is unset
+        Ok(value) => value.get::<(f64, f64, f64)>()
+            .filter(|(r, g, b)| [r, g, b].iter().all(|c| (0.0..=1.0).contains(*c)))
+            .map(|(r, g, b)| {
+                format!(
+                    "#{:02x}{:02x}{:02x}",
+                    (r * 255.0).round() as u8,
+                    (g * 255.0).round() as u8,
+                    (b * 255.0).round() as u8,
+                )
+            }),
+        Err(e) => {
+            log::debug!("Portal accent-color unavailable: {}", e);
+            None
+        }
+    };
+
+    Ok(PortalAppearance { color_scheme, accent_color })
+}
+
+/// Call `on_change` with the key whenever an appearance setting changes.
+///
+/// The callback runs on the thread-default main context, i.e. the GTK main
+/// loop when called from the UI.
+pub fn watch_portal_appearance(on_change: impl Fn(&str) + 'static) -> Result<()> {
+    let connection = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE)?;
+    connection.signal_subscribe(
+        Some(PORTAL_BUS_NAME),
+        Some(PORTAL_SETTINGS_INTERFACE),
+        Some("SettingChanged"),
+        Some(PORTAL_OBJECT_PATH),
+        Some(APPEARANCE_NAMESPACE),
+        gio::DBusSignalFlags::NONE,
+        move |_, _, _, _, _, parameters| {
+            // SettingChanged carries (namespace, key, value)
+            if let Some(key) = parameters.try_child_value(1).and_then(|key| key.get::<String>()) {
+                log::debug!("Portal appearance setting changed: {}", key);
+                on_change(&key);
+            }
+        },
+    );
+    Ok(())
+}
+
+/// Call `on_change` with the new values when the GTK theme or its dark
+/// preference changes.
+///
+/// Does nothing unless GTK is running on this thread. The values are read
+/// here because other threads only see `settings.ini`.
+pub fn watch_gtk_theme_settings(on_change: impl Fn(GtkThemeSettings) + 'static) {
+    if !gtk4::is_initialized_main_thread() {
+        return;
+    }
+    let Some(settings) = gtk4::Settings::default() else {
+        return;
+    };
+
+    let on_change = std::rc::Rc::new(on_change);
+    let callback = on_change.clone();
+    settings.connect_gtk_application_prefer_dark_theme_notify(move |settings| callback(live_settings(settings)));
+    settings.connect_gtk_theme_name_notify(move |settings| on_change(live_settings(settings)));
+}
+
+fn read_setting(connection: &gio::DBusConnection, key: &str) -> Result<glib::Variant> {
+    let parameters = (APPEARANCE_NAMESPACE, key).to_variant();
+    let call = |method: &str| {
+        connection.call_sync(
+            Some(PORTAL_BUS_NAME),
+            PORTAL_OBJECT_PATH,
+            PORTAL_SETTINGS_INTERFACE,
+            method,
+            Some(&parameters),
+            None,
+            gio::DBusCallFlags::NONE,
+            PORTAL_TIMEOUT_MS,
+            gio::Cancellable::NONE,
+        )
+    };
+
+    // ReadOne was added in version 2 of the interface; older portals only
+    // provide the deprecated Read, which wraps the value in an extra variant.
+    let reply = match call("ReadOne") {
+        Err(e) if remote_error(&e) == Some(UNKNOWN_METHOD) => call("Read"),
+        reply => reply,
+    }?;
+
+    let mut value = reply.try_child_value(0)
+        .ok_or_else(|| anyhow!("Empty reply for {}.{}", APPEARANCE_NAMESPACE, key))?;
+    // `as_variant` is only defined for `v`; on anything else GLib complains
+    while value.is_type(glib::VariantTy::VARIANT) {
+        value = value.as_variant()
+            .ok_or_else(|| anyhow!("Invalid reply for {}.{}", APPEARANCE_NAMESPACE, key))?;
+    }
+    Ok(value)
+}
+
+/// The D-Bus name of an error the other end replied with, or `None` for
+/// local ones such as timeouts
+fn remote_error(error: &glib::Error) -> Option<&str> {
+    // GDBus keeps the name at the start of the message
+    error.message().strip_prefix("GDBus.Error:")?.split(':').next()
+}
+
+/// Whether `error` came from a portal that is there, just without the setting
+fn portal_answered(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<glib::Error>() {
+        Some(error) => remote_error(error) == Some(SETTING_NOT_FOUND),
+        None => true,
+    }
+}
+
+/// Read the GTK theme name and dark preference.
+///
+/// Uses the live `GtkSettings` when GTK is running on this thread and falls
+/// back to the user's `settings.ini` otherwise (e.g. for `--print-theme`).
+pub fn gtk_theme_settings() -> GtkThemeSettings {
+    if gtk4::is_initialized_main_thread() {
+        if let Some(settings) = gtk4::Settings::default() {
+            return live_settings(&settings);
+        }
+    }
+
+    let mut result = GtkThemeSettings::default();
+    let Some(config_dir) = dirs::config_dir() else {
+        return result;
+    };
+
+    for gtk_dir in ["gtk-4.0", "gtk-3.0"] {
+        let Ok(content) = std::fs::read_to_string(config_dir.join(gtk_dir).join("settings.ini")) else {
+            continue;
+        };
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "gtk-theme-name" if result.theme_name.is_none() => {
+                    result.theme_name = Some(value.to_string());
+                }
+                "gtk-application-prefer-dark-theme" if result.prefer_dark.is_none() => {
+                    result.prefer_dark = Some(value == "1" || value.eq_ignore_ascii_case("true"));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    result
+}
+
+fn live_settings(settings: &gtk4::Settings) -> GtkThemeSettings {
+    GtkThemeSettings {
+        theme_name: settings.gtk_theme_name().map(|name| name.to_string()),
+        prefer_dark: Some(settings.is_gtk_application_prefer_dark_theme()),
+    }
+}
+
+/// Collect the `@define-color` values of an installed GTK theme.
+///
+/// References to other named colors are resolved; values built with GTK's
+/// color functions (`mix()`, `shade()`, ...) are skipped.
+pub fn theme_named_colors(theme_name: &str, dark: bool) -> BTreeMap<String, String> {
+    let mut stylesheets = vec!["gtk.css"];
+    if dark {
+        stylesheets.insert(0, "gtk-dark.css");
+    }
+
+    let mut raw = BTreeMap::new();
+    for theme_dir in theme_dirs(theme_name) {
+        for gtk_dir in ["gtk-4.0", "gtk-3.0"] {
+            for stylesheet in &stylesheets {
+                let path = theme_dir.join(gtk_dir).join(stylesheet);
+                if let Ok(content) = std::fs::read_to_string(&path) {
+                    log::debug!("Reading named colors from {}", path.display());
+                    parse_define_colors(&content, &mut raw);
+                    return resolve_named_colors(&raw);
+                }
+            }
+        }
+    }
+
+    raw
+}
+
+fn theme_dirs(theme_name: &str) -> Vec<PathBuf> {
+    let mut dirs_to_search = Vec::new();
+    if let Some(data_dir) = dirs::data_dir() {
+        dirs_to_search.push(data_dir.join("themes"));
+    }
+    if let Some(home_dir) = dirs::home_dir() {
+        dirs_to_search.push(home_dir.join(".themes"));
+    }
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .unwrap_or_else(|_| "/usr/local/share:/usr/share".to_string());
+    dirs_to_search.extend(
+        data_dirs.split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| PathBuf::from(dir).join("themes")),
+    );
+
+    dirs_to_search.into_iter()
+        .map(|dir| dir.join(theme_name))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+fn parse_define_colors(content: &str, colors: &mut BTreeMap<String, String>) {
+    for statement in content.split(';') {
+        let statement = statement.trim();
+        let Some(start) = statement.find("@define-color") else {
+            continue;
+        };
+        let mut parts = statement[start + "@define-color".len()..].trim().splitn(2, char::is_whitespace);
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            // Later definitions win, matching CSS semantics
+            colors.insert(name.to_string(), value.trim().to_string());
+        }
+    }
+}
+
+fn resolve_named_colors(raw: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    let mut resolved = BTreeMap::new();
+    for name in raw.keys() {
+        let mut value = &raw[name];
+        // Follow @references, bounded to guard against cycles
+        for _ in 0..8 {
+            match value.strip_prefix('@').and_then(|reference| raw.get(reference)) {
+                Some(next) => value = next,
+                None => break,
+            }
+        }
+        let is_function = value.contains('(') && !value.starts_with("rgb");
+        if !value.starts_with('@') && !is_function {
+            resolved.insert(name.clone(), value.clone());
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::time::{Duration, Instant};
+
+    /// What the mock portal implements
+    #[derive(Clone, Copy, PartialEq)]
+    enum Methods {
+        ReadOne,
+        /// Only the deprecated method, like portals before version 2
+        Read,
+        Both,
+        /// Both, but ReadOne never answers, like a wedged portal
+        Silent,
+    }
+
+    /// A private message bus with a mock settings portal on it.
+    ///
+    /// gio 0.18 doesn't wrap `GTestDBus`, so this starts `dbus-daemon` the
+    /// way it does. The portal runs its own main loop on a second thread,
+    /// since `read_portal_appearance` blocks the calling one.
+    struct MockPortal {
+        daemon: Child,
+        main_loop: glib::MainLoop,
+        address: String,
+        /// Method calls the portal received
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl MockPortal {
+        /// Serve `settings` through `methods`
+        fn start(methods: Methods, settings: Vec<(&'static str, glib::Variant)>) -> Self {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("dbus-daemon is needed for the portal tests");
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+            let address = address.trim().to_string();
+
+            // Variants aren't Send; pass them across as text
+            let settings: Vec<(&str, String)> = settings.into_iter()
+                .map(|(key, value)| (key, value.print(true).to_string()))
+                .collect();
+            let (ready, started) = mpsc::channel();
+            let server_address = address.clone();
+            let calls = Arc::new(AtomicUsize::new(0));
+            let received = calls.clone();
+            std::thread::spawn(move || {
+                let context = glib::MainContext::new();
+                context.with_thread_default(|| {
+                    let connection = connect(&server_address);
+                    let names: &[&str] = match methods {
+                        Methods::ReadOne => &["ReadOne"],
+                        Methods::Read => &["Read"],
+                        Methods::Both | Methods::Silent => &["ReadOne", "Read"],
+                    };
+                    let declarations: String = names.iter()
+                        .map(|name| format!(
+                            r#"<method name="{}">
+                                <arg type="s" name="namespace" direction="in"/>
+                                <arg type="s" name="key" direction="in"/>
+                                <arg type="v" name="value" direction="out"/>
+                            </method>"#,
+                            name,
+                        ))
+                        .collect();
+                    let xml = format!(
+                        r#"<node><interface name="{}">{}</interface></node>"#,
+                        PORTAL_SETTINGS_INTERFACE, declarations,
+                    );
+                    let interface = gio::DBusNodeInfo::for_xml(&xml).unwrap()
+                        .lookup_interface(PORTAL_SETTINGS_INTERFACE)
+                        .unwrap();
+                    connection.register_object(
+                        PORTAL_OBJECT_PATH,
+                        &interface,
+                        move |_, _, _, _, method, parameters, invocation| {
+                            received.fetch_add(1, Ordering::SeqCst);
+                            if methods == Methods::Silent && method == "ReadOne" {
+                                // Never reply; the caller times out
+                                return;
+                            }
+                            let (namespace, key) = parameters.get::<(String, String)>().unwrap();
+                            let value = settings.iter()
+                                .find(|(name, _)| namespace == APPEARANCE_NAMESPACE && *name == key)
+                                .map(|(_, value)| glib::Variant::parse(None, value).unwrap());
+                            match value {
+                                // Read wraps the value in one more variant than ReadOne
+                                Some(value) if method == "Read" => {
+                                    invocation.return_value(Some(&(value.to_variant(),).to_variant()));
+                                }
+                                Some(value) => invocation.return_value(Some(&(value,).to_variant())),
+                                None => invocation.return_dbus_error(
+                                    "org.freedesktop.portal.Error.NotFound",
+                                    "Requested setting not found",
+                                ),
+                            }
+                        },
+                        |_, _, _, _, _| unreachable!("the mock portal has no properties"),
+                        |_, _, _, _, _, _| unreachable!("the mock portal has no properties"),
+                    ).unwrap();
+                    connection.call_sync(
+                        Some("org.freedesktop.DBus"),
+                        "/org/freedesktop/DBus",
+                        "org.freedesktop.DBus",
+                        "RequestName",
+                        Some(&(PORTAL_BUS_NAME, 4u32).to_variant()), // DO_NOT_QUEUE
+                        None,
+                        gio::DBusCallFlags::NONE,
+                        -1,
+                        gio::Cancellable::NONE,
+                    ).unwrap();
+
+                    let main_loop = glib::MainLoop::new(Some(&context), false);
+                    ready.send(main_loop.clone()).unwrap();
+                    main_loop.run();
+                }).unwrap();
+            });
+
+            let main_loop = started.recv().unwrap();
+            Self { daemon, main_loop, address, calls }
+        }
+
+        fn read(&self) -> PortalAppearance {
+            read_portal_appearance(&connect(&self.address)).unwrap()
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Drop for MockPortal {
+        fn drop(&mut self) {
+            self.main_loop.quit();
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    fn connect(address: &str) -> gio::DBusConnection {
+        gio::DBusConnection::for_address_sync(
+            address,
+            gio::DBusConnectionFlags::AUTHENTICATION_CLIENT | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+            None,
+            gio::Cancellable::NONE,
+        ).unwrap()
+    }
+
+    fn appearance(color_scheme: u32, accent: (f64, f64, f64)) -> Vec<(&'static str, glib::Variant)> {
+        vec![("color-scheme", color_scheme.to_variant()), ("accent-color", accent.to_variant())]
+    }
+
+    #[test]
+    fn reads_appearance_with_read_one() {
+        let portal = MockPortal::start(Methods::ReadOne, appearance(1, (0.2078, 0.5176, 0.8941)));
+        let appearance = portal.read();
+        assert_eq!(appearance.color_scheme, Some(ColorScheme::PreferDark));
+        assert_eq!(appearance.accent_color.as_deref(), Some("#3584e4"));
+    }
+
+    #[test]
+    fn falls_back_to_read_on_old_portals() {
+        let portal = MockPortal::start(Methods::Read, appearance(2, (0.8784, 0.1059, 0.1412)));
+        let appearance = portal.read();
+        assert_eq!(appearance.color_scheme, Some(ColorScheme::PreferLight));
+        assert_eq!(appearance.accent_color.as_deref(), Some("#e01b24"));
+    }
+
+    #[test]
+    fn unset_and_missing_settings_are_none() {
+        // An accent color outside 0..1 means the user hasn't picked one
+        let portal = MockPortal::start(Methods::ReadOne, appearance(0, (-1.0, -1.0, -1.0)));
+        let appearance = portal.read();
+        assert_eq!(appearance.color_scheme, Some(ColorScheme::NoPreference));
+        assert_eq!(appearance.accent_color, None);
+
+        let portal = MockPortal::start(Methods::ReadOne, Vec::new());
+        let appearance = portal.read();
+        assert_eq!(appearance.color_scheme, None);
+        assert_eq!(appearance.accent_color, None);
+    }
+
+    #[test]
+    fn only_an_unknown_read_one_is_retried_with_read() {
+        // A missing setting isn't a missing method
+        let portal = MockPortal::start(Methods::Both, Vec::new());
+        portal.read();
+        assert_eq!(portal.calls(), 2);
+    }
+
+    #[test]
+    fn a_silent_portal_is_waited_on_once() {
+        let portal = MockPortal::start(Methods::Silent, appearance(1, (0.2, 0.5, 0.9)));
+        let started = Instant::now();
+        assert!(read_portal_appearance(&connect(&portal.address)).is_err());
+        // Neither Read nor the accent color is tried after the timeout
+        assert_eq!(portal.calls(), 1);
+        assert!(started.elapsed() < Duration::from_millis(2 * PORTAL_TIMEOUT_MS as u64));
+    }
+
+    #[test]
+    fn desktop_prefers_the_portal_then_gtk() {
+        let desktop = |color_scheme, theme_name: Option<&str>, prefer_dark| Desktop {
+            appearance: PortalAppearance { color_scheme, accent_color: None },
+            gtk: GtkThemeSettings { theme_name: theme_name.map(str::to_string), prefer_dark },
+        };
+        std::env::remove_var("GTK_THEME");
+        assert!(!desktop(Some(ColorScheme::PreferLight), Some("Adwaita-dark"), Some(true)).prefers_dark());
+        assert!(desktop(Some(ColorScheme::NoPreference), Some("Adwaita-dark"), None).prefers_dark());
+        assert!(!desktop(None, Some("Adwaita"), Some(false)).prefers_dark());
+        assert!(desktop(None, None, None).prefers_dark());
+    }
+}
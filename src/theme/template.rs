@@ -0,0 +1,212 @@
+// CSS template rendering
+//
+// Placeholders are written `{name}`, optionally followed by color functions:
+// `{primary|alpha(0.3)}`, `{surface|lighten(10%)}`, `{primary|mix(danger, 50%)}`.
+// Braces that don't start with a bare identifier (rule blocks,
+// `{ opacity: 0; }`, ...) are copied through untouched, so templates are
+// plain CSS files.
+
+use super::color::{self, Color};
+use anyhow::{Result, anyhow, bail};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A placeholder value; only colors accept color functions
+#[derive(Debug, Clone)]
+pub enum Value {
+    Color(Color),
+    Text(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Color(color) => color.fmt(f),
+            Value::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+/// Values available to a template, keyed by placeholder name
+pub type Variables = BTreeMap<String, Value>;
+
+pub fn render(template: &str, variables: &Variables) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        match after.find('}').map(|end| &after[..end]).filter(|expression| is_expression(expression)) {
+            Some(expression) => {
+                let value = evaluate(expression, variables).map_err(|e| {
+                    let offset = template.len() - rest.len() + start;
+                    let line = template[..offset].matches('\n').count() + 1;
+                    anyhow!("{} in '{{{}}}' on line {}", e, expression, line)
+                })?;
+                output.push_str(&value.to_string());
+                rest = &after[expression.len() + 1..];
+            }
+            None => {
+                output.push('{');
+                rest = after;
+            }
+        }
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Placeholders start with an identifier that is either alone or followed by `|`
+fn is_expression(content: &str) -> bool {
+    let name = content.split('|').next().unwrap_or_default();
+    is_identifier(name)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn evaluate(expression: &str, variables: &Variables) -> Result<Value> {
+    let mut filters = expression.split('|');
+    let name = filters.next().unwrap_or_default();
+    let mut value = variables.get(name).cloned()
+        .ok_or_else(|| anyhow!("Unknown placeholder '{}'", name))?;
+
+    for filter in filters {
+        let filter = filter.trim();
+        let (function, args) = filter.strip_suffix(')')
+            .and_then(|call| call.split_once('('))
+            .ok_or_else(|| anyhow!("Malformed color function '{}'", filter))?;
+
+        let Value::Color(color) = value else {
+            bail!("'{}' is not a color", name);
+        };
+        let args = args.trim();
+        value = Value::Color(match function.trim() {
+            "alpha" => color.with_alpha(color::parse_alpha(args)?),
+            "lighten" => color.lighten(color::parse_alpha(args)?),
+            "darken" => color.darken(color::parse_alpha(args)?),
+            "mix" => {
+                let (other, amount) = args.rsplit_once(',')
+                    .ok_or_else(|| anyhow!("mix() takes a color and an amount"))?;
+                color.mix(resolve_color(other.trim(), variables)?, color::parse_alpha(amount.trim())?)
+            }
+            other => bail!("Unknown color function '{}'", other),
+        });
+    }
+
+    Ok(value)
+}
+
+/// A color argument: another placeholder name or a literal color
+fn resolve_color(arg: &str, variables: &Variables) -> Result<Color> {
+    match variables.get(arg) {
+        Some(Value::Color(color)) => Ok(*color),
+        Some(Value::Text(_)) => bail!("'{}' is not a color", arg),
+        None if is_identifier(arg) && Color::parse(arg).is_err() => bail!("Unknown placeholder '{}'", arg),
+        None => Color::parse(arg),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> Variables {
+        Variables::from([
+            ("primary".to_string(), Value::Color(Color::rgb(0x89, 0xb4, 0xfa))),
+            ("danger".to_string(), Value::Color(Color::rgb(0xf3, 0x8b, 0xa8))),
+            ("transition".to_string(), Value::Text("all 200ms ease".to_string())),
+        ])
+    }
+
+    fn rendered(template: &str) -> String {
+        render(template, &variables()).unwrap()
+    }
+
+    fn error(template: &str) -> String {
+        render(template, &variables()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn placeholders_are_substituted() {
+        assert_eq!(
+            rendered("button { color: {primary}; transition: {transition}; }"),
+            "button { color: #89b4fa; transition: all 200ms ease; }",
+        );
+        assert_eq!(rendered("{primary}{danger}"), "#89b4fa#f38ba8");
+    }
+
+    #[test]
+    fn css_braces_are_copied() {
+        let css = concat!(
+            "@keyframes fade {\n",
+            "  from { opacity: 0; }\n",
+            "  to {opacity:1}\n",
+            "}\n",
+            ".empty {}\n",
+            ".compact{color:red}\n",
+            "button {\n",
+            "  border-color: {danger};\n",
+            "}\n",
+        );
+        assert_eq!(rendered(css), css.replace("{danger}", "#f38ba8"));
+    }
+
+    #[test]
+    fn unknown_placeholders_report_their_line() {
+        assert_eq!(
+            error("window {}\nbutton { color: {primry}; }"),
+            "Unknown placeholder 'primry' in '{primry}' on line 2",
+        );
+    }
+
+    #[test]
+    fn unclosed_braces_are_copied() {
+        assert_eq!(rendered("button { color: {primary"), "button { color: {primary");
+        assert_eq!(rendered("{primary\nbutton { color: red; }"), "{primary\nbutton { color: red; }");
+    }
+
+    #[test]
+    fn color_functions() {
+        let primary = Color::rgb(0x89, 0xb4, 0xfa);
+        let danger = Color::rgb(0xf3, 0x8b, 0xa8);
+        for (template, expected) in [
+            ("{primary|alpha(0.3)}", primary.with_alpha(0.3)),
+            ("{primary|alpha(30%)}", primary.with_alpha(0.3)),
+            ("{primary|lighten(10%)}", primary.lighten(0.1)),
+            ("{primary|darken(0.1)}", primary.darken(0.1)),
+            ("{primary|lighten(100%)}", Color::rgb(255, 255, 255)),
+            ("{primary|darken(100%)}", Color::rgb(0, 0, 0)),
+            ("{primary|mix(danger, 50%)}", primary.mix(danger, 0.5)),
+            ("{primary|mix(danger,0.5)}", primary.mix(danger, 0.5)),
+            ("{primary|mix(#000000, 25%)}", primary.mix(Color::rgb(0, 0, 0), 0.25)),
+            ("{primary|mix(rgb(0, 0, 0), 25%)}", primary.mix(Color::rgb(0, 0, 0), 0.25)),
+            ("{primary|mix(white, 100%)}", Color::rgb(255, 255, 255)),
+            ("{primary|mix(danger, 50%)|alpha(0.4)}", primary.mix(danger, 0.5).with_alpha(0.4)),
+        ] {
+            assert_eq!(rendered(template), expected.to_string(), "{}", template);
+        }
+        assert_eq!(rendered("{primary|alpha(0.3)}"), "rgba(137, 180, 250, 0.3)");
+    }
+
+    #[test]
+    fn color_function_errors() {
+        for (template, expected) in [
+            ("{primary|saturate(10%)}", "Unknown color function 'saturate' in '{primary|saturate(10%)}' on line 1"),
+            ("{primary|alpha}", "Malformed color function 'alpha' in '{primary|alpha}' on line 1"),
+            ("{primary|alpha(1.5)}", "alpha '1.5' is out of range in '{primary|alpha(1.5)}' on line 1"),
+            ("{primary|mix(danger)}", "mix() takes a color and an amount in '{primary|mix(danger)}' on line 1"),
+            ("{primary|mix(secondry, 50%)}", "Unknown placeholder 'secondry' in '{primary|mix(secondry, 50%)}' on line 1"),
+            ("{primary|mix(transition, 50%)}", "'transition' is not a color in '{primary|mix(transition, 50%)}' on line 1"),
+            ("{transition|alpha(0.5)}", "'transition' is not a color in '{transition|alpha(0.5)}' on line 1"),
+        ] {
+            assert_eq!(error(template), expected, "{}", template);
+        }
+    }
+}
@@ -0,0 +1,354 @@
+// Terminal color configs: Xresources, kitty, foot and alacritty
+//
+// Every format is read into the same slot table pywal uses: `background`,
+// `foreground`, `cursor` and `color0`-`color15`. Colors are left as strings;
+// the caller maps slots to roles and validates them.
+
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+pub type Slots = BTreeMap<String, String>;
+
+/// Terminal config formats understood as theme sources
+pub const FORMATS: &[&str] = &["xresources", "kitty", "foot", "alacritty"];
+
+/// How deep kitty `include` directives are followed
+const MAX_INCLUDE_DEPTH: usize = 4;
+
+/// ANSI color names in palette order, as used by alacritty
+const ANSI_NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+/// Where each format keeps its colors when `file_path` isn't set
+pub fn default_path(format: &str) -> Option<PathBuf> {
+    match format {
+        "xresources" => dirs::home_dir().map(|home| home.join(".Xresources")),
+        "kitty" => dirs::config_dir().map(|config| config.join("kitty").join("kitty.conf")),
+        "foot" => dirs::config_dir().map(|config| config.join("foot").join("foot.ini")),
+        "alacritty" => dirs::config_dir().map(|config| config.join("alacritty").join("alacritty.toml")),
+        _ => None,
+    }
+}
+
+pub fn read_slots(format: &str, path: &Path) -> Result<Slots> {
+    read(format, path, &mut Vec::new())
+}
+
+/// Every file reading `path` looks at: the config itself and, for kitty, the
+/// files it includes, whether or not they exist
+pub fn files(format: &str, path: &Path) -> Vec<PathBuf> {
+    let mut files = vec![path.to_path_buf()];
+    let _ = read(format, path, &mut files);
+    files
+}
+
+fn read(format: &str, path: &Path, files: &mut Vec<PathBuf>) -> Result<Slots> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read {} config {}: {}", format, path.display(), e))?;
+
+    let mut slots = Slots::new();
+    match format {
+        "xresources" => parse_xresources(&content, &mut slots),
+        "kitty" => parse_kitty(&content, path, 0, &mut slots, files),
+        "foot" => parse_foot(&content, &mut slots),
+        "alacritty" => parse_alacritty(&content, &mut slots)?,
+        _ => return Err(anyhow!("Unknown terminal config format: {}", format)),
+    }
+
+    if slots.is_empty() {
+        return Err(anyhow!("No colors found in {} config {}", format, path.display()));
+    }
+    Ok(slots)
+}
+
+/// `*.background: #1e1e2e`, `URxvt*color1: ...`, with `#define` macros
+fn parse_xresources(content: &str, slots: &mut Slots) {
+    let mut defines = HashMap::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('!') {
+            continue;
+        }
+
+        if let Some(define) = line.strip_prefix("#define") {
+            let mut parts = define.split_whitespace();
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                defines.insert(name.to_string(), value.to_string());
+            }
+            continue;
+        }
+
+        let Some((resource, value)) = line.split_once(':') else {
+            continue;
+        };
+        // The slot is the last component of `class.name`, `*name` or `name`
+        let name = resource.trim().rsplit(['.', '*']).next().unwrap_or_default();
+        let slot = match name {
+            "background" | "foreground" => name.to_string(),
+            "cursorColor" => "cursor".to_string(),
+            _ if is_color_slot(name) => name.to_string(),
+            _ => continue,
+        };
+
+        let value = value.trim();
+        let value = defines.get(value).map(String::as_str).unwrap_or(value);
+        // Later definitions win, as they do for xrdb
+        slots.insert(slot, x11_color(value));
+    }
+}
+
+/// Convert X11's `rgb:rr/gg/bb` notation to hex; other values pass through
+fn x11_color(value: &str) -> String {
+    let Some(channels) = value.strip_prefix("rgb:") else {
+        return value.to_string();
+    };
+    // Channels have 1-4 hex digits each; scale them to 8 bits
+    let hex: Option<String> = channels.split('/')
+        .map(|channel| {
+            if !(1..=4).contains(&channel.len()) {
+                return None;
+            }
+            let scaled = u32::from_str_radix(channel, 16).ok()? * 255 / ((1 << (4 * channel.len())) - 1);
+            Some(format!("{:02x}", scaled))
+        })
+        .collect();
+    match hex {
+        Some(hex) if hex.len() == 6 => format!("#{}", hex),
+        _ => value.to_string(),
+    }
+}
+
+/// `color1 #f38ba8`, following `include` directives. Included files are
+/// added to `files`.
+fn parse_kitty(content: &str, path: &Path, depth: usize, slots: &mut Slots, files: &mut Vec<PathBuf>) {
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let (Some(key), Some(value)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+
+        match key {
+            "include" if depth < MAX_INCLUDE_DEPTH => {
+                let include = path.parent().unwrap_or(Path::new(".")).join(value);
+                files.push(include.clone());
+                match std::fs::read_to_string(&include) {
+                    Ok(included) => parse_kitty(&included, &include, depth + 1, slots, files),
+                    Err(e) => log::debug!("Skipping kitty include {}: {}", include.display(), e),
+                }
+            }
+            "background" | "foreground" | "cursor" => {
+                slots.insert(key.to_string(), value.to_string());
+            }
+            _ if is_color_slot(key) => {
+                slots.insert(key.to_string(), value.to_string());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The `[colors]` section of foot.ini: `background=1e1e2e`, `regular0`-`7`, `bright0`-`7`
+fn parse_foot(content: &str, slots: &mut Slots) {
+    let mut in_colors = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            in_colors = section.trim() == "colors";
+            continue;
+        }
+        if !in_colors {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        // foot writes colors as bare hex
+        let value = format!("#{}", value.trim().trim_start_matches('#'));
+        let key = key.trim();
+        let slot = if let Some(index) = ansi_index(key, "regular") {
+            format!("color{}", index)
+        } else if let Some(index) = ansi_index(key, "bright") {
+            format!("color{}", index + 8)
+        } else if matches!(key, "background" | "foreground") {
+            key.to_string()
+        } else {
+            continue;
+        };
+        slots.insert(slot, value);
+    }
+}
+
+/// `index` in `regular<index>` or `bright<index>`, for the 8 ANSI colors
+fn ansi_index(key: &str, prefix: &str) -> Option<u8> {
+    key.strip_prefix(prefix)
+        .and_then(|index| index.parse::<u8>().ok())
+        .filter(|&index| index < 8)
+}
+
+/// `[colors.primary]`, `[colors.normal]`, `[colors.bright]` and `[colors.cursor]`
+fn parse_alacritty(content: &str, slots: &mut Slots) -> Result<()> {
+    let config: toml::Value = toml::from_str(content)?;
+    let Some(colors) = config.get("colors") else {
+        return Ok(());
+    };
+    let color = |table: &str, key: &str| {
+        colors.get(table)
+            .and_then(|table| table.get(key))
+            .and_then(|value| value.as_str())
+            // alacritty also accepts 0xRRGGBB
+            .map(|value| match value.strip_prefix("0x") {
+                Some(hex) => format!("#{}", hex),
+                None => value.to_string(),
+            })
+    };
+
+    for key in ["background", "foreground"] {
+        if let Some(value) = color("primary", key) {
+            slots.insert(key.to_string(), value);
+        }
+    }
+    if let Some(value) = color("cursor", "cursor") {
+        slots.insert("cursor".to_string(), value);
+    }
+    for (offset, table) in [(0, "normal"), (8, "bright")] {
+        for (index, name) in ANSI_NAMES.iter().enumerate() {
+            if let Some(value) = color(table, name) {
+                slots.insert(format!("color{}", index + offset), value);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// `color0` through `color15`
+fn is_color_slot(name: &str) -> bool {
+    name.strip_prefix("color")
+        .and_then(|index| index.parse::<u8>().ok())
+        .is_some_and(|index| index < 16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot<'a>(slots: &'a Slots, name: &str) -> Option<&'a str> {
+        slots.get(name).map(String::as_str)
+    }
+
+    #[test]
+    fn xresources_with_defines_and_x11_colors() {
+        let mut slots = Slots::new();
+        parse_xresources(concat!(
+            "! Catppuccin\n",
+            "#define base #1e1e2e\n",
+            "*.background: base\n",
+            "URxvt*foreground: rgb:cd/d6/f4\n",
+            "*cursorColor: #f5e0dc\n",
+            "*color1: #f38ba8\n",
+            "*color1: #eba0ac\n",
+            "*color16: #ffffff\n",
+            "*font: monospace\n",
+        ), &mut slots);
+
+        assert_eq!(slot(&slots, "background"), Some("#1e1e2e"));
+        assert_eq!(slot(&slots, "foreground"), Some("#cdd6f4"));
+        assert_eq!(slot(&slots, "cursor"), Some("#f5e0dc"));
+        assert_eq!(slot(&slots, "color1"), Some("#eba0ac"));
+        assert_eq!(slots.len(), 4);
+    }
+
+    #[test]
+    fn x11_colors_scale_to_eight_bits() {
+        assert_eq!(x11_color("rgb:f/0/8"), "#ff0088");
+        assert_eq!(x11_color("rgb:ff/00/80"), "#ff0080");
+        assert_eq!(x11_color("rgb:fff/000/800"), "#ff007f");
+        assert_eq!(x11_color("rgb:ffff/0000/8080"), "#ff0080");
+        assert_eq!(x11_color("rgb:fffff/0/0"), "rgb:fffff/0/0");
+        assert_eq!(x11_color("rgb:ff/00"), "rgb:ff/00");
+        assert_eq!(x11_color("red"), "red");
+    }
+
+    #[test]
+    fn kitty_follows_includes() {
+        let dir = std::env::temp_dir().join(format!("departure-kitty-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("theme.conf"), "background #1e1e2e\ncolor4 #89b4fa\n").unwrap();
+        let config = dir.join("kitty.conf");
+        std::fs::write(&config, "# comment\ninclude theme.conf\ninclude missing.conf\ncolor4 #74c7ec\nfont_size 11\n").unwrap();
+
+        let slots = read_slots("kitty", &config);
+        let files = files("kitty", &config);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let slots = slots.unwrap();
+        assert_eq!(files, [config, dir.join("theme.conf"), dir.join("missing.conf")]);
+        assert_eq!(slot(&slots, "background"), Some("#1e1e2e"));
+        // Settings after the include override it
+        assert_eq!(slot(&slots, "color4"), Some("#74c7ec"));
+        assert_eq!(slots.len(), 2);
+    }
+
+    #[test]
+    fn foot_reads_only_the_colors_section() {
+        let mut slots = Slots::new();
+        parse_foot(concat!(
+            "[main]\n",
+            "background=000000\n",
+            "[colors]\n",
+            "background=1e1e2e\n",
+            "foreground = #cdd6f4\n",
+            "regular1=f38ba8\n",
+            "bright7=a6adc8\n",
+        ), &mut slots);
+
+        assert_eq!(slot(&slots, "background"), Some("#1e1e2e"));
+        assert_eq!(slot(&slots, "foreground"), Some("#cdd6f4"));
+        assert_eq!(slot(&slots, "color1"), Some("#f38ba8"));
+        assert_eq!(slot(&slots, "color15"), Some("#a6adc8"));
+    }
+
+    #[test]
+    fn foot_ignores_indices_past_the_ansi_colors() {
+        let mut slots = Slots::new();
+        parse_foot("[colors]\nregular8=ffffff\nbright8=ffffff\nbright250=ffffff\nregular-1=ffffff\n", &mut slots);
+        assert!(slots.is_empty(), "{:?}", slots);
+    }
+
+    #[test]
+    fn alacritty_tables_and_hex_prefixes() {
+        let mut slots = Slots::new();
+        parse_alacritty(concat!(
+            "[colors.primary]\n",
+            "background = '0x1e1e2e'\n",
+            "foreground = '#cdd6f4'\n",
+            "[colors.cursor]\n",
+            "cursor = '#f5e0dc'\n",
+            "[colors.normal]\n",
+            "red = '#f38ba8'\n",
+            "[colors.bright]\n",
+            "white = '#a6adc8'\n",
+        ), &mut slots).unwrap();
+
+        assert_eq!(slot(&slots, "background"), Some("#1e1e2e"));
+        assert_eq!(slot(&slots, "foreground"), Some("#cdd6f4"));
+        assert_eq!(slot(&slots, "cursor"), Some("#f5e0dc"));
+        assert_eq!(slot(&slots, "color1"), Some("#f38ba8"));
+        assert_eq!(slot(&slots, "color15"), Some("#a6adc8"));
+    }
+
+    #[test]
+    fn invalid_alacritty_toml_is_an_error() {
+        assert!(parse_alacritty("[colors", &mut Slots::new()).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+// Wallpaper theme source: pick a seed color straight from the image
+
+use super::cache;
+use super::command;
+use super::color::Color;
+use super::quantize;
+use anyhow::{Result, anyhow};
+use gtk4::gdk_pixbuf::Pixbuf;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const CACHE_FILE: &str = "wallpaper-seed.json";
+
+/// Images are downscaled to this size before quantizing; more pixels barely
+/// change the result but cost a lot of time
+const SAMPLE_SIZE: i32 = 128;
+const MAX_COLORS: usize = 128;
+
+#[derive(Serialize, Deserialize)]
+struct CachedSeed {
+    path: PathBuf,
+    mtime: u128,
+    seed: String,
+}
+
+/// Extract the best seed color from a wallpaper, cached on the file's mtime.
+pub fn seed_from_wallpaper(path: &Path) -> Result<Color> {
+    let mtime = cache::mtime(path)
+        .ok_or_else(|| anyhow!("Wallpaper does not exist: {}", path.display()))?;
+
+    if let Some(cached) = cache::read::<CachedSeed>(CACHE_FILE) {
+        if cached.path == path && cached.mtime == mtime {
+            if let Ok(seed) = Color::parse(&cached.seed) {
+                log::debug!("Using cached wallpaper seed {} for {}", seed, path.display());
+                return Ok(seed);
+            }
+        }
+    }
+
+    let pixels = load_pixels(path)?;
+    let population = quantize::quantize(&pixels, MAX_COLORS);
+    let seed = quantize::score(&population).into_iter().next().unwrap_or_else(|| {
+        log::info!("No suitable seed color in {}, using fallback", path.display());
+        quantize::FALLBACK_SEED
+    });
+    log::info!("Extracted seed color {} from wallpaper {}", seed, path.display());
+
+    let entry = CachedSeed { path: path.to_path_buf(), mtime, seed: seed.to_hex() };
+    if let Err(e) = cache::write(CACHE_FILE, &entry) {
+        log::debug!("Failed to cache wallpaper seed: {}", e);
+    }
+
+    Ok(seed)
+}
+
+fn load_pixels(path: &Path) -> Result<Vec<Color>> {
+    let pixbuf = Pixbuf::from_file_at_scale(path, SAMPLE_SIZE, SAMPLE_SIZE, true)
+        .map_err(|e| anyhow!("Failed to load wallpaper {}: {}", path.display(), e))?;
+
+    let bytes = pixbuf.read_pixel_bytes();
+    let channels = pixbuf.n_channels() as usize;
+    let rowstride = pixbuf.rowstride() as usize;
+    let has_alpha = pixbuf.has_alpha();
+
+    let mut pixels = Vec::with_capacity((pixbuf.width() * pixbuf.height()) as usize);
+    for y in 0..pixbuf.height() as usize {
+        for x in 0..pixbuf.width() as usize {
+            let offset = y * rowstride + x * channels;
+            let Some(pixel) = bytes.get(offset..offset + channels) else {
+                continue;
+            };
+            // Skip transparent areas, they aren't part of what the user sees
+            if has_alpha && pixel[3] < 255 {
+                continue;
+            }
+            pixels.push(Color::rgb(pixel[0], pixel[1], pixel[2]));
+        }
+    }
+
+    Ok(pixels)
+}
+
+/// Ask a wallpaper daemon for the current image, e.g. `swww query`.
+///
+/// Understands swww's `... currently displaying: image: <path>` lines and
+/// otherwise takes the first line of output as the path.
+pub fn wallpaper_from_command(command: &str, timeout: Duration) -> Result<PathBuf> {
+    log::debug!("Executing wallpaper command: {}", command);
+
+    let stdout = command::run(command, timeout)?;
+    let path = stdout.lines()
+        .find_map(|line| line.split_once("image: ").map(|(_, path)| path.trim()))
+        .or_else(|| stdout.lines().map(str::trim).find(|line| !line.is_empty()))
+        .ok_or_else(|| anyhow!("Wallpaper command printed no path"))?;
+
+    Ok(PathBuf::from(path))
+}
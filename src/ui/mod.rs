@@ -1,13 +1,21 @@
 // UI module
 
-use crate::config::{Config, ActionConfig};
-use crate::theme::{ThemeManager, ThemeColors};
+use crate::config::{Config, ActionConfig, LayoutType};
+use crate::theme::{StartupTheme, ThemeManager, ThemeColors, ThemeUpdate};
 use gtk4::prelude::*;
 use gtk4::{Application, ApplicationWindow, Box, Button, Dialog, Label, Orientation};
 use gtk4_layer_shell::{Edge, Layer, LayerShell};
 use anyhow::Result;
 use std::process::Command;
-
+use std::sync::mpsc::{channel, TryRecvError};
+use std::time::Duration;
+
+/// The CSS providers installed on the display, plus the colors they were built from
+struct ThemeProviders {
+    generated: gtk4::CssProvider,
+    user: gtk4::CssProvider,
+    colors: ThemeColors,
+}
 
 pub struct DepartureApp {
     app: Application,
@@ -65,8 +73,10 @@ impl DepartureApp {
         window.set_margin(Edge::Right, 0);
 
         // Get theme colors and apply CSS
-        let colors = self.theme_manager.get_colors()?;
-        self.apply_theme(&window, &colors)?;
+        // Cached or without waiting on the theme command, which restyles the menu when it finishes
+        let theme = self.theme_manager.get_startup_theme()?;
+        let providers = self.apply_theme(&window, &theme.colors, &theme.css)?;
+        self.start_theme_watcher(&window, providers, &theme);
 
         // Create overlay container for dimming effect
         let overlay = gtk4::Overlay::new();
@@ -108,11 +118,10 @@ impl DepartureApp {
     }
 
     fn create_main_layout(&self, colors: &ThemeColors) -> Result<Box> {
-        let orientation = match self.config.layout.layout_type.as_str() {
-            "vertical" => Orientation::Vertical,
-            "horizontal" => Orientation::Horizontal,
-            "grid" => Orientation::Horizontal, // We'll handle grid separately
-            _ => Orientation::Horizontal,
+        let orientation = match self.config.layout.layout_type {
+            LayoutType::Vertical => Orientation::Vertical,
+            LayoutType::Horizontal => Orientation::Horizontal,
+            LayoutType::Grid => Orientation::Horizontal, // We'll handle grid separately
         };
 
         let main_box = Box::new(orientation, self.config.layout.button_spacing as i32);
@@ -121,7 +130,7 @@ impl DepartureApp {
         main_box.set_hexpand(false);
         main_box.set_vexpand(false);
 
-        if self.config.layout.layout_type == "grid" {
+        if self.config.layout.layout_type == LayoutType::Grid {
             self.create_grid_layout(&main_box, colors)?;
         } else {
             self.create_linear_layout(&main_box, colors)?;
@@ -350,19 +359,93 @@ impl DepartureApp {
         Ok(())
     }
 
-    fn apply_theme(&self, window: &ApplicationWindow, colors: &ThemeColors) -> Result<()> {
-        let css = self.theme_manager.generate_css(colors);
-        
-        let provider = gtk4::CssProvider::new();
-        provider.load_from_data(&css);
-
+    fn apply_theme(&self, window: &ApplicationWindow, colors: &ThemeColors, css: &str) -> Result<ThemeProviders> {
         let display = gtk4::prelude::WidgetExt::display(window);
+
+        let generated = gtk4::CssProvider::new();
+        generated.load_from_data(css);
         gtk4::style_context_add_provider_for_display(
             &display,
-            &provider,
+            &generated,
             gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION,
         );
 
-        Ok(())
+        // The user stylesheet goes above the generated one so its rules always win
+        let user = gtk4::CssProvider::new();
+        if let Some(css) = self.theme_manager.user_stylesheet() {
+            user.load_from_data(&css);
+        }
+        gtk4::style_context_add_provider_for_display(
+            &display,
+            &user,
+            gtk4::STYLE_PROVIDER_PRIORITY_USER,
+        );
+
+        Ok(ThemeProviders { generated, user, colors: colors.clone() })
     }
-}
+
+    /// Restyle the open window whenever the theme watchers report a change.
+    fn start_theme_watcher(&self, window: &ApplicationWindow, providers: ThemeProviders, theme: &StartupTheme) {
+        let (sender, receiver) = channel();
+        if theme.command_pending {
+            self.theme_manager.refresh_command_colors(sender.clone());
+        }
+        if let Some(scheme) = &theme.cached_scheme {
+            self.theme_manager.check_cached_scheme(scheme.clone(), sender.clone());
+        }
+        if let Err(e) = self.theme_manager.start_watcher(sender.clone()) {
+            log::warn!("Failed to start theme file watcher: {}", e);
+        }
+        if let Err(e) = self.theme_manager.start_scheme_watcher(sender) {
+            log::warn!("Failed to follow the desktop's color scheme: {}", e);
+        }
+
+        let theme_manager = self.theme_manager.clone();
+        let display = gtk4::prelude::WidgetExt::display(window);
+        let mut providers = providers;
+
+        // The watcher lives on a background thread; poll its channel from the
+        // GTK main loop so the provider swap happens on the UI thread.
+        gtk4::glib::timeout_add_local(Duration::from_millis(100), move || {
+            let mut regenerate = false;
+            let mut reload_user = false;
+            loop {
+                match receiver.try_recv() {
+                    Ok(ThemeUpdate::Colors(colors)) => {
+                        providers.colors = *colors;
+                        regenerate = true;
+                    }
+                    Ok(ThemeUpdate::Template) => regenerate = true,
+                    Ok(ThemeUpdate::Stylesheet) => reload_user = true,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        if !regenerate && !reload_user {
+                            return gtk4::glib::ControlFlow::Break;
+                        }
+                        break;
+                    }
+                }
+            }
+
+            if regenerate {
+                let provider = gtk4::CssProvider::new();
+                provider.load_from_data(&theme_manager.generate_css(&providers.colors));
+                gtk4::style_context_add_provider_for_display(
+                    &display,
+                    &provider,
+                    gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION,
+                );
+                gtk4::style_context_remove_provider_for_display(&display, &providers.generated);
+                providers.generated = provider;
+                log::info!("Applied updated theme");
+            }
+
+            if reload_user {
+                providers.user.load_from_data(&theme_manager.user_stylesheet().unwrap_or_default());
+                log::info!("Reloaded user stylesheet");
+            }
+
+            gtk4::glib::ControlFlow::Continue
+        });
+    }
+}
\ No newline at end of file